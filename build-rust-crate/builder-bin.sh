@@ -1,5 +1,6 @@
 source $stdenv/setup
 source $builderCommon
+shopt -s nullglob
 
 declare -A buildFlagsMap
 declare -A binPathMap
@@ -8,7 +9,8 @@ dontInstall=1
 
 addBin() {
     local name="$1" path="$2" binEdition="$3"
-    local -a pathCandidates
+    shift 3
+    local -a pathCandidates requiredFeatures=("$@")
 
     if [[ -z "$name" ]]; then
         echo "Name of the binary target is not specified"
@@ -46,6 +48,7 @@ addBin() {
 
     printf "Found binary %q at %q\n" "$name" "$path"
     binPathMap["$path"]=1
+    hasRequiredFeatures binary "$name" "${requiredFeatures[@]}" || return 0
 
     # TODO: Other flags.
     buildFlagsMap["$name"]="$path --crate-name ${name//-/_} -C metadata=$rustcMeta-$name"
@@ -57,29 +60,31 @@ addBin() {
 configurePhase() {
     runHook preConfigure
 
-    convertCargoToml
+    loadCargoToml
 
-    globalEdition="$(jq --raw-output '.package.edition // ""' "$cargoTomlJson")"
-    pkgName="$(jq --raw-output '.package.name // ""' "$cargoTomlJson")"
+    globalEdition="$(cargoTomlGet package.edition)"
+    pkgName="$(cargoTomlGet package.name)"
 
     # For packages with the 2015 edition, the default for auto-discovery is false if at least one target is
     # manually defined in Cargo.toml. Beginning with the 2018 edition, the default is always true.
     # See: https://doc.rust-lang.org/cargo/reference/cargo-targets.html#target-auto-discovery
     autoDiscovery=
     if [[
-        "$(jq --raw-output '.package.autobins' "$cargoTomlJson")" != false &&
+        "$(cargoTomlGet package.autobins)" != false &&
         ( "${globalEdition:-2015}" != 2015 || ${#buildFlagsMap[@]} = 0 )
     ]]; then
         autoDiscovery=1
     fi
 
-    local binsStr
-    while read -r name; do
-        read -r path
-        read -r binEdition
-        addBin "$name" "$path" "$binEdition"
-        # Don't strip whitespace.
-    done < <(jq --raw-output '.bin // [] | .[] | .name // "", .path // "", .edition // ""' "$cargoTomlJson")
+    local -a binNames binPaths binEditions requiredFeatures
+    local i
+    mapfile -t binNames < <(cargoTomlGet 'bin[].name' --lines)
+    mapfile -t binPaths < <(cargoTomlGet 'bin[].path' --lines)
+    mapfile -t binEditions < <(cargoTomlGet 'bin[].edition' --lines)
+    for i in "${!binNames[@]}"; do
+        mapfile -t requiredFeatures < <(cargoTomlGet "bin[$i].required-features" --lines)
+        addBin "${binNames[i]}" "${binPaths[i]}" "${binEditions[i]}" "${requiredFeatures[@]}"
+    done
 
     if [[ -n "$autoDiscovery" ]]; then
         if [[ -f src/main.rs && -z ${binPathMap[src/main.rs]} ]]; then
@@ -98,7 +103,7 @@ configurePhase() {
 
     if [[ ${#buildFlagsMap[@]} = 0 ]]; then
         echo "No binaries to be built"
-        mkdir $out
+        mkdir $out ${debug:+$debug}
         exit 0
     fi
 
@@ -128,17 +133,28 @@ buildPhase() {
     mkdir -p $out/bin
 
     local binName
+    local -a linkArgs
     for binName in "${!buildFlagsMap[@]}"; do
         export CARGO_CRATE_NAME="$binName"
         export CARGO_BIN_NAME="$binName"
+        linkArgs=()
+        importLinkArgs linkArgs "$buildDrv" bin "$binName"
         runRustc "Building binary $binName" \
             ${buildFlagsMap["$binName"]} \
             --crate-type bin \
             --out-dir $out/bin \
             $buildFlags \
-            "${buildFlagsArray[@]}"
+            "${buildFlagsArray[@]}" \
+            "${linkArgs[@]}"
     done
 
+    # Split debuginfo from `-Csplit-debuginfo`.
+    local -a splitDebuginfo=($out/bin/*.dwp $out/bin/*.dwo)
+    if [[ -n "$debug" && ${#splitDebuginfo[@]} -gt 0 ]]; then
+        mkdir -p $debug/lib/debug
+        mv -t $debug/lib/debug "${splitDebuginfo[@]}"
+    fi
+
     runHook postBuild
 }
 
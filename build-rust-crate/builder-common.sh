@@ -55,6 +55,27 @@ addFeatures() {
     done
 }
 
+# Check whether all `required-features` of a target are enabled.
+# `dep/feat` requires the feature `feat` of the dependency `dep`, looked up in `depFeatures`.
+# Otherwise, print a note and return 1.
+# https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-required-features-field
+hasRequiredFeatures() {
+    local kind="$1" name="$2" feat enabled
+    shift 2
+    for feat in "$@"; do
+        if [[ "$feat" == */* ]]; then
+            feat="${feat/\?\//\/}"
+            enabled="$depFeatures"
+        else
+            enabled="$features"
+        fi
+        if [[ " $enabled " != *" $feat "* ]]; then
+            printf "Skipped %s %q, which requires feature %q\n" "$kind" "$name" "$feat"
+            return 1
+        fi
+    done
+}
+
 importBuildOut() {
     local var="$1" cvar="$2" drv="$3" flags
     [[ ! -e "$drv/rust-support/build-stdout" ]] && return
@@ -78,6 +99,41 @@ importBuildOut() {
     fi
 }
 
+# Import link args from the build script for a target.
+# Usage: importLinkArgs <var> <drv> <kind> [<bin-name>]
+# <kind> is one of `cdylib`, `bin`, `test`, `example` and `bench`.
+# https://doc.rust-lang.org/cargo/reference/build-scripts.html#rustc-link-arg
+importLinkArgs() {
+    local var="$1" drv="$2" kind="$3" binName="${4:-}" file flags
+    local -a files=(rustc-link-args)
+    case "$kind" in
+        cdylib)
+            ;;
+        bin)
+            files+=(rustc-link-args-bins "rustc-link-args-bin-$binName")
+            ;;
+        test)
+            files+=(rustc-link-args-tests)
+            ;;
+        example)
+            files+=(rustc-link-args-examples)
+            ;;
+        bench)
+            files+=(rustc-link-args-benches)
+            ;;
+        *)
+            echo "Unknown target kind for link args: $kind"
+            exit 1
+            ;;
+    esac
+    for file in "${files[@]}"; do
+        if [[ -e "$drv/rust-support/$file" ]]; then
+            mapfile -t flags <"$drv/rust-support/$file"
+            eval "$var"'+=("${flags[@]}")'
+        fi
+    done
+}
+
 runRustc() {
     local msg="$1"
     shift
@@ -85,10 +141,29 @@ runRustc() {
     $RUSTC "$@"
 }
 
-convertCargoToml() {
-    local cargoToml="${1:-"$(pwd)/Cargo.toml"}"
-    cargoTomlJson="$(mktemp "$(dirname "$cargoToml")/Cargo.json.XXX")"
-    toml2json <"$cargoToml" >"$cargoTomlJson"
+loadCargoToml() {
+    local workspaceRoot
+    local -a toml2jsonFlags=(--cargo-manifest)
+    cargoToml="${1:-"$(pwd)/Cargo.toml"}"
+
+    # Inherit `workspace = true` fields from the workspace root manifest.
+    if [[ -n "${workspaceManifest:-}" ]]; then
+        workspaceRoot="$(mktemp -d)"
+        ln -s "$workspaceManifest" "$workspaceRoot/Cargo.toml"
+        toml2jsonFlags+=(--workspace-root "$workspaceRoot")
+    fi
+
+    # Normalize only once, which also fails early on invalid manifest.
+    cargoTomlJson="$(mktemp --suffix=.json)"
+    toml2json "${toml2jsonFlags[@]}" "$cargoToml" >"$cargoTomlJson"
+}
+
+# Query a field of the normalized Cargo.toml. See `toml2json --help` for the path syntax.
+# Usage: cargoTomlGet <path> [--lines]
+cargoTomlGet() {
+    local path="$1"
+    shift
+    toml2json --json --get "$path" "$@" "$cargoTomlJson"
 }
 
 # https://doc.rust-lang.org/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-crates
@@ -96,11 +171,11 @@ setCargoCommonBuildEnv() {
     # CARGO_CRATE_NAME is set outside since targets have individual crate names.
 
     # export CARGO=
-    CARGO_MANIFEST_DIR="$(dirname "$cargoTomlJson")"
+    CARGO_MANIFEST_DIR="$(dirname "$cargoToml")"
     export CARGO_MANIFEST_DIR
 
-    CARGO_PKG_NAME="$(jq --raw-output '.package.name // ""' "$cargoTomlJson")"
-    CARGO_PKG_VERSION="$(jq --raw-output '.package.version // ""' "$cargoTomlJson")"
+    CARGO_PKG_NAME="$(cargoTomlGet package.name)"
+    CARGO_PKG_VERSION="$(cargoTomlGet package.version)"
     if [[ -z "CARGO_PKG_NAME" ]]; then
         echo "Package name must be set"
         exit 1
@@ -110,11 +185,13 @@ setCargoCommonBuildEnv() {
         exit 1
     fi
 
-    CARGO_PKG_AUTHORS="$(jq --raw-output '.package.authors // [] | join(":")' "$cargoTomlJson")"
-    CARGO_PKG_DESCRIPTION="$(jq --raw-output '.package.description // ""' "$cargoTomlJson")"
-    CARGO_PKG_HOMEPAGE="$(jq --raw-output '.package.homepage // ""' "$cargoTomlJson")"
-    CARGO_PKG_LICENSE="$(jq --raw-output '.package.license // ""' "$cargoTomlJson")"
-    CARGO_PKG_LICENSE_FILE="$(jq --raw-output '.package."license-file" // ""' "$cargoTomlJson")"
+    local -a authors
+    mapfile -t authors < <(cargoTomlGet package.authors --lines)
+    CARGO_PKG_AUTHORS="$(IFS=:; echo "${authors[*]}")"
+    CARGO_PKG_DESCRIPTION="$(cargoTomlGet package.description)"
+    CARGO_PKG_HOMEPAGE="$(cargoTomlGet package.homepage)"
+    CARGO_PKG_LICENSE="$(cargoTomlGet package.license)"
+    CARGO_PKG_LICENSE_FILE="$(cargoTomlGet package.license-file)"
     export CARGO_PKG_NAME CARGO_PKG_VERSION CARGO_PKG_AUTHORS CARGO_PKG_DESCRIPTION \
         CARGO_PKG_HOMEPAGE CARGO_PKG_LICENSE CARGO_PKG_LICENSE_FILE
 
@@ -0,0 +1,101 @@
+source $stdenv/setup
+source $builderCommon
+shopt -s nullglob
+
+dontInstall=1
+
+configurePhase() {
+    runHook preConfigure
+
+    loadCargoToml
+
+    libSrc="$(cargoTomlGet lib.path)"
+    if [[ -z "$libSrc" && -e src/lib.rs ]]; then
+        libSrc=src/lib.rs
+    fi
+    if [[ ! -e "$libSrc" ]]; then
+        echo "No library to be documented"
+        mkdir -p $out/share/doc
+        if [[ -n "$docBase" ]]; then
+            cp -r --no-preserve=mode -t $out/share/doc "$docBase"/share/doc/.
+        fi
+        exit 0
+    fi
+    if [[ -n "$doctest" && "$(cargoTomlGet lib.doctest)" == false ]]; then
+        echo "Doctests are disabled"
+        mkdir -p $out
+        exit 0
+    fi
+
+    crateName="$(cargoTomlGet lib.name)"
+    if [[ -z "$crateName" ]]; then
+        crateName="$(cargoTomlGet package.name)"
+        crateName="${crateName//-/_}"
+    fi
+
+    edition="$(cargoTomlGet lib.edition)"
+    if [[ -z "$edition" ]]; then
+        edition="$(cargoTomlGet package.edition)"
+    fi
+    if [[ -n "$edition" ]]; then
+        buildFlagsArray+=(--edition="$edition")
+    fi
+    if [[ -n "$procMacro" ]]; then
+        buildFlagsArray+=(--extern=proc_macro)
+    fi
+    if [[ -n "$capLints" ]]; then
+        buildFlagsArray+=(--cap-lints="$capLints")
+    fi
+
+    if [[ -n "$doctest" ]]; then
+        # Doctests are linked against the library itself, and can use dev-dependencies.
+        addExternFlags buildFlagsArray link "::$libOutDrv:$libDevDrv" $dependencies $devDependencies
+        collectTransDeps "$(pwd)/deps-closure" $dependencies $devDependencies
+    else
+        addExternFlags buildFlagsArray meta $dependencies
+        collectTransDeps "$(pwd)/deps-closure" $dependencies
+    fi
+    buildFlagsArray+=(-Ldependency="$(pwd)/deps-closure")
+
+    # rustdoc doesn't accept `-l`. Native libraries are linked through the rlib of the crate.
+    local -a importFlags cdylibBuildFlagsArray
+    local flag
+    importBuildOut importFlags cdylibBuildFlagsArray "$buildDrv"
+    for flag in "${importFlags[@]}"; do
+        [[ "$flag" == -l* ]] || buildFlagsArray+=("$flag")
+    done
+
+    addFeatures buildFlagsArray $features
+    setCargoCommonBuildEnv
+    export CARGO_CRATE_NAME="$crateName"
+
+    runHook postConfigure
+}
+
+buildPhase() {
+    runHook preBuild
+
+    if [[ -n "$doctest" ]]; then
+        echo "Running doctests: RUSTDOC ${buildFlagsArray[*]@Q}"
+        $RUSTDOC "$libSrc" \
+            --test \
+            --crate-name="$crateName" \
+            "${buildFlagsArray[@]}"
+        mkdir -p $out
+    else
+        mkdir -p $out/share/doc
+        # Documenting on top of docs of other crates makes rustdoc link to them and merge search indexes.
+        if [[ -n "$docBase" ]]; then
+            cp -r --no-preserve=mode -t $out/share/doc "$docBase"/share/doc/.
+        fi
+        echo "Documenting: RUSTDOC ${buildFlagsArray[*]@Q}"
+        $RUSTDOC "$libSrc" \
+            --crate-name="$crateName" \
+            --out-dir="$out/share/doc" \
+            "${buildFlagsArray[@]}"
+    fi
+
+    runHook postBuild
+}
+
+genericBuild
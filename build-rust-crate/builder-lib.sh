@@ -7,9 +7,9 @@ buildFlagsArray+=( -Cmetadata="$rustcMeta" )
 configurePhase() {
     runHook preConfigure
 
-    convertCargoToml
+    loadCargoToml
 
-    libSrc="$(jq --raw-output '.lib.path // ""' "$cargoTomlJson")"
+    libSrc="$(cargoTomlGet lib.path)"
     if [[ -z "$libSrc" && -e src/lib.rs ]]; then
         libSrc=src/lib.rs
     fi
@@ -19,19 +19,32 @@ configurePhase() {
         exit 0
     fi
 
-    crateName="$(jq --raw-output '.lib.name // (.package.name // "" | gsub("-"; "_"))' "$cargoTomlJson")"
+    crateName="$(cargoTomlGet lib.name)"
+    if [[ -z "$crateName" ]]; then
+        crateName="$(cargoTomlGet package.name)"
+        crateName="${crateName//-/_}"
+    fi
     if [[ -z "$crateName" ]]; then
         echo "Package name must be set"
         exit 1
     fi
 
-    edition="$(jq --raw-output '.package.edition // .lib.edition // ""' "$cargoTomlJson")"
+    edition="$(cargoTomlGet lib.edition)"
+    if [[ -z "$edition" ]]; then
+        edition="$(cargoTomlGet package.edition)"
+    fi
     if [[ -n "$edition" ]]; then
         buildFlagsArray+=(--edition="$edition")
     fi
 
-    mapfile -t crateTypes < <(jq --raw-output '.lib."crate-type" // ["lib"] | .[]' "$cargoTomlJson")
-    cargoTomlIsProcMacro="$(jq --raw-output 'if .lib."proc-macro" then "1" else "" end' "$cargoTomlJson")"
+    mapfile -t crateTypes < <(cargoTomlGet lib.crate-type --lines)
+    if (( ${#crateTypes[@]} == 0 )); then
+        crateTypes=(lib)
+    fi
+    cargoTomlIsProcMacro=
+    if [[ "$(cargoTomlGet lib.proc-macro)" == true ]]; then
+        cargoTomlIsProcMacro=1
+    fi
     if [[ "$cargoTomlIsProcMacro" != "$procMacro" ]]; then
         echo "Cargo.toml says proc-macro = ${cargoTomlIsProcMacro:-0} but it is built with procMacro = ${procMacro:-0}"
         exit 1
@@ -68,6 +81,9 @@ configurePhase() {
 
     declare -a cdylibBuildFlagsArray
     importBuildOut buildFlagsArray cdylibBuildFlagsArray "$buildDrv"
+    if [[ -n "$buildCdylib" ]]; then
+        importLinkArgs cdylibBuildFlagsArray "$buildDrv" cdylib
+    fi
     # FIXME: cargo include cdylib flags for all crate-types once cdylib is included.
     buildFlagsArray+=( "${cdylibBuildFlagsArray[@]}" )
 
@@ -0,0 +1,181 @@
+source $stdenv/setup
+source $builderCommon
+shopt -s nullglob
+
+# Build test, example or bench targets, according to `$targetKind`.
+# See: https://doc.rust-lang.org/cargo/reference/cargo-targets.html
+
+declare -A buildFlagsMap
+declare -A targetPathMap
+
+dontInstall=1
+
+case "$targetKind" in
+    test)
+        targetDir=tests
+        ;;
+    example)
+        targetDir=examples
+        ;;
+    bench)
+        targetDir=benches
+        ;;
+    *)
+        echo "Unknown target kind: $targetKind"
+        exit 1
+        ;;
+esac
+
+addTarget() {
+    local name="$1" path="$2" targetEdition="$3" harness="$4"
+    shift 4
+    local -a requiredFeatures=("$@")
+
+    if [[ -z "$name" ]]; then
+        echo "Name of the $targetKind target is not specified"
+        exit 1
+    fi
+
+    if [[ -z "$path" ]]; then
+        if [[ -f "$targetDir/$name.rs" ]]; then
+            path="$targetDir/$name.rs"
+        elif [[ -f "$targetDir/$name/main.rs" ]]; then
+            path="$targetDir/$name/main.rs"
+        else
+            echo "Cannot guess path of $targetKind target"
+            exit 1
+        fi
+    fi
+
+    printf "Found %s %q at %q\n" "$targetKind" "$name" "$path"
+    targetPathMap["$path"]=1
+    hasRequiredFeatures "$targetKind" "$name" "${requiredFeatures[@]}" || return 0
+
+    buildFlagsMap["$name"]="$path --crate-name ${name//-/_} -C metadata=$rustcMeta-$targetKind-$name"
+    if [[ -n "${targetEdition:=$globalEdition}" ]]; then
+        buildFlagsMap["$name"]+=" --edition $targetEdition"
+    fi
+    # Tests and benches use the libtest harness by default.
+    if [[ "$targetKind" != example && "$harness" != false ]]; then
+        buildFlagsMap["$name"]+=" --test"
+    else
+        buildFlagsMap["$name"]+=" --crate-type bin"
+    fi
+}
+
+configurePhase() {
+    runHook preConfigure
+
+    loadCargoToml
+
+    globalEdition="$(cargoTomlGet package.edition)"
+
+    # Unit tests of the library.
+    libSrc=
+    if [[ "$targetKind" == test ]]; then
+        libSrc="$(cargoTomlGet lib.path)"
+        if [[ -z "$libSrc" && -e src/lib.rs ]]; then
+            libSrc=src/lib.rs
+        fi
+    fi
+    if [[ -n "$libSrc" && "$(cargoTomlGet lib.test)" != false ]]; then
+        libCrateName="$(cargoTomlGet lib.name)"
+        if [[ -z "$libCrateName" ]]; then
+            libCrateName="$(cargoTomlGet package.name)"
+            libCrateName="${libCrateName//-/_}"
+        fi
+        libEdition="$(cargoTomlGet lib.edition)"
+        printf "Found library unit tests at %q\n" "$libSrc"
+    else
+        libSrc=
+    fi
+
+    # Integration tests, examples or benches.
+    local -a names paths editions harnesses requiredFeatures
+    local i
+    mapfile -t names < <(cargoTomlGet "$targetKind[].name" --lines)
+    mapfile -t paths < <(cargoTomlGet "$targetKind[].path" --lines)
+    mapfile -t editions < <(cargoTomlGet "$targetKind[].edition" --lines)
+    mapfile -t harnesses < <(cargoTomlGet "$targetKind[].harness" --lines)
+    for i in "${!names[@]}"; do
+        mapfile -t requiredFeatures < <(cargoTomlGet "$targetKind[$i].required-features" --lines)
+        addTarget "${names[i]}" "${paths[i]}" "${editions[i]}" "${harnesses[i]}" "${requiredFeatures[@]}"
+    done
+
+    # See: https://doc.rust-lang.org/cargo/reference/cargo-targets.html#target-auto-discovery
+    if [[
+        "$(cargoTomlGet "package.auto${targetDir}")" != false &&
+        ( "${globalEdition:-2015}" != 2015 || ${#names[@]} = 0 )
+    ]]; then
+        local f name
+        for f in "$targetDir"/*; do
+            name="${f##*/}"
+            if [[ "$f" = *.rs && -f "$f" ]]; then
+                [[ -n "${targetPathMap["$f"]}" ]] || addTarget "${name%.rs}" "$f" "" ""
+            elif [[ -f "$f/main.rs" ]]; then
+                [[ -n "${targetPathMap["$f/main.rs"]}" ]] || addTarget "$name" "$f/main.rs" "" ""
+            fi
+        done
+    fi
+
+    # Actually unused.
+    declare -a cdylibBuildFlagsArray
+
+    # Dev-dependencies are only available for tests, examples and benches.
+    addExternFlags buildFlagsArray link $dependencies $devDependencies
+    addFeatures buildFlagsArray $features
+    importBuildOut buildFlagsArray cdylibBuildFlagsArray "$buildDrv"
+    importLinkArgs buildFlagsArray "$buildDrv" "$targetKind"
+    setCargoCommonBuildEnv
+
+    depsClosure="$(mktemp -d)"
+    collectTransDeps "$depsClosure" $dependencies $devDependencies
+    buildFlagsArray+=(-Ldependency="$depsClosure")
+
+    runHook postConfigure
+}
+
+buildPhase() {
+    runHook preBuild
+
+    mkdir -p $out/bin
+
+    if [[ -n "$libSrc" ]]; then
+        local -a libFlagsArray=()
+        if [[ -n "${libEdition:=$globalEdition}" ]]; then
+            libFlagsArray+=(--edition="$libEdition")
+        fi
+        if [[ -n "$procMacro" ]]; then
+            libFlagsArray+=(--extern=proc_macro)
+        fi
+        export CARGO_CRATE_NAME="$libCrateName"
+        runRustc "Building library unit tests" \
+            "$libSrc" \
+            --test \
+            --crate-name="$libCrateName" \
+            -Cmetadata="$rustcMeta-test" \
+            -o "$out/bin/unittests-$libCrateName" \
+            $buildFlags \
+            "${libFlagsArray[@]}" \
+            "${buildFlagsArray[@]}"
+    fi
+
+    # Other targets link the library of current crate, if exists.
+    if [[ -e "$libDevDrv"/lib ]]; then
+        addExternFlags buildFlagsArray link "::$libOutDrv:$libDevDrv"
+    fi
+
+    local name
+    for name in "${!buildFlagsMap[@]}"; do
+        export CARGO_CRATE_NAME="${name//-/_}"
+        runRustc "Building $targetKind $name" \
+            ${buildFlagsMap["$name"]} \
+            -o "$out/bin/$name" \
+            $buildFlags \
+            "${buildFlagsArray[@]}"
+    done
+
+    runHook postBuild
+}
+
+genericBuild
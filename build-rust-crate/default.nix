@@ -1,20 +1,30 @@
-{ lib, nocargo-lib, stdenv, buildPackages, rust, toml2json, jq }:
+{ lib, nocargo-lib, stdenv, buildPackages, rust, toml2json, build-script-out }:
 { pname
 , version
 , src
 , rustc ? buildPackages.rustc
 , links ? null
-# [ { rename = "foo" /* or null */; drv = <derivation>; } ]
+# [ { name = "foo"; rename = "foo" /* or null */; drv = <derivation>; } ]
+# `name` is the key in Cargo.toml, defaulting to the package name.
 , dependencies ? []
+# Dependencies only for tests, examples and benches, in the same format as `dependencies`.
+, devDependencies ? []
 # Normal dependencies with non empty `links`, which will propagate `DEP_<LINKS>_<META>` environments to build script.
 , linksDependencies ? dependencies
 , buildDependencies ? []
 , features ? []
 , profile ? {}
 , capLints ? null
+# Extra flags for all rustc invocations, including build scripts. They are part of the metadata hash.
+, rustflags ? []
 , buildFlags ? []
+# Environments from `[env]` of `.cargo/config.toml`, for rustc, build scripts and tests.
+# Variables set by builders take precedence.
+, cargoEnv ? {}
 , buildScriptBuildFlags ? []
 , procMacro ? false
+# The workspace root Cargo.toml, to fill in `workspace = true` fields of `[package]`. (null or path)
+, workspaceManifest ? null
 
 , nativeBuildInputs ? []
 , propagatedBuildInputs ? []
@@ -26,7 +36,9 @@ let
   mkRustcMeta = dependencies: features: let
     deps = lib.concatMapStrings (dep: dep.drv.rustcMeta) dependencies;
     feats = lib.concatStringsSep ";" features;
-    final = "${pname} ${version} ${feats} ${deps}";
+    flags = lib.optionalString (rustflags != []) " ${lib.concatStringsSep " " rustflags}";
+    # Outputs of different toolchains must not collide.
+    final = "${pname} ${version} ${feats} ${deps}${flags} rustc-${lib.getVersion rustc}";
   in
     lib.substring 0 16 (builtins.hashString "sha256" final);
 
@@ -42,8 +54,13 @@ let
   ]);
   toDevDrvs = map ({ drv, ... }: drv.dev);
 
+  # Enabled features of dependencies as `dep/feat`, for `required-features` of targets.
+  mkDepFeatures = lib.concatMap ({ drv, ... }@dep:
+    map (feat: "${dep.name or drv.pname}/${feat}") (drv.features or []));
+
   buildDeps = mkDeps buildDependencies;
   libDeps = mkDeps dependencies;
+  devDeps = mkDeps devDependencies;
 
   builderCommon = ./builder-common.sh;
 
@@ -57,7 +74,8 @@ let
     []
     ++ lib.optional (p.opt-level or 0 != 0) "-Copt-level=${toString p.opt-level}"
     ++ lib.optional (p.debug or false != false) "-Cdebuginfo=${if p.debug == true then "2" else toString p.debug}"
-    # TODO: `-Cstrip` is not handled since stdenv will always strip them.
+    # `strip` is handled by stdenv. See `stripArgs`.
+    ++ lib.optional (p ? split-debuginfo) "-Csplit-debuginfo=${p.split-debuginfo}"
     ++ lib.optional (p ? debug-assertions) "-Cdebug-assertions=${convertBool "no" "yes" p.debug-assertions}"
     ++ lib.optional (p ? overflow-checks) "-Coverflow-checks=${convertBool "no" "yes" p.debug-assertions}"
     ++ lib.optional (!procMacro && p.lto or false != false) "-Clto=${if p.lto == true then "fat" else p.lto}"
@@ -73,10 +91,12 @@ let
     buildFlags =
       profileToRustcFlags p
       ++ lib.optional (capLints != null) "--cap-lints=${capLints}"
+      ++ rustflags
       ++ buildFlags;
 
     buildScriptBuildFlags =
       profileToRustcFlags (p.build-override or {})
+      ++ rustflags
       ++ buildScriptBuildFlags;
 
     # Build script environments.
@@ -85,19 +105,32 @@ let
     DEBUG = p.debug or 0 != 0;
   };
 
+  # https://doc.rust-lang.org/cargo/reference/profiles.html#strip
+  # Debuginfo is stripped by default if it's not requested, as cargo does.
+  # Stripping is left to stdenv, so that the separated debuginfo can be kept in the `debug` output.
+  stripArgs = p: let
+    hasDebuginfo = !lib.elem (p.debug or false) [ false 0 "none" ];
+    strip = convertBool "none" "symbols" (p.strip or (if hasDebuginfo then "none" else "debuginfo"));
+  in {
+    dontStrip = strip == "none";
+    stripAllList = lib.optionals (strip == "symbols") [ "bin" ];
+    separateDebugInfo = hasDebuginfo && strip != "none";
+  };
+
   profile' = convertProfile profile;
   buildProfile' = convertProfile (profile.build-override or {});
 
-  commonArgs = {
+  commonArgs = cargoEnv // {
     inherit pname version src;
 
-    nativeBuildInputs = [ toml2json jq ] ++ nativeBuildInputs;
+    nativeBuildInputs = [ toml2json ] ++ nativeBuildInputs;
 
     sharedLibraryExt = stdenv.hostPlatform.extensions.sharedLibrary;
 
     inherit capLints;
 
     RUSTC = "${rustc}/bin/rustc";
+    RUSTDOC = "${rustc}/bin/rustdoc";
   } // removeAttrs args [
     "pname"
     "version"
@@ -105,11 +138,14 @@ let
     "rustc"
     "links"
     "dependencies"
+    "devDependencies"
     "linksDependencies"
     "buildDependencies"
     "features"
     "profile"
     "capLints"
+    "rustflags"
+    "cargoEnv"
     "buildFlags"
     "buildScriptBuildFlags"
     "procMacro"
@@ -143,7 +179,9 @@ let
     # Build script may produce object files and static libraries which should not be modified.
     dontFixup = true;
 
-  } // commonArgs // cargoCfgs // buildProfile');
+  } // commonArgs // {
+    nativeBuildInputs = commonArgs.nativeBuildInputs ++ [ build-script-out ];
+  } // cargoCfgs // buildProfile');
 
   libDrv = stdenv.mkDerivation ({
     name = "rust_${pname}-${version}";
@@ -173,10 +211,86 @@ let
     buildInputs = toDevDrvs dependencies;
 
     dependencies = libDeps;
+    depFeatures = mkDepFeatures dependencies;
+  } // commonArgs // profile' // stripArgs profile);
+
+  # Tests, examples or benches, which link the library and dev-dependencies.
+  mkTargetsDrv = targetKind: stdenv.mkDerivation ({
+    name = "rust_${pname}-${version}-${targetKind}";
+    builder = ./builder-targets.sh;
+    inherit propagatedBuildInputs builderCommon buildDrv features rustcMeta procMacro targetKind;
+
+    libOutDrv = libDrv.out;
+    libDevDrv = libDrv.dev;
+
+    buildInputs = toDevDrvs (dependencies ++ devDependencies);
+
+    dependencies = libDeps;
+    devDependencies = devDeps;
+    depFeatures = mkDepFeatures (dependencies ++ devDependencies);
   } // commonArgs // profile');
 
+  testDrv = mkTargetsDrv "test";
+
+  # Run all tests in the source directory, as `cargo test` does.
+  checkDrv = stdenv.mkDerivation (cargoEnv // {
+    name = "rust_${pname}-${version}-check";
+    inherit src;
+
+    dontConfigure = true;
+    buildPhase = ''
+      runHook preBuild
+
+      export CARGO_MANIFEST_DIR="$(pwd)"
+      for test in ${testDrv}/bin/*; do
+        [[ -e "$test" ]] || continue
+        echo "Running $test"
+        "$test"
+      done
+
+      runHook postBuild
+    '';
+    installPhase = ''
+      touch $out
+    '';
+  });
+
+  # Documentation of the library. Documenting on top of `docBase`, another doc derivation,
+  # links to crates documented there and merges them into one tree.
+  mkDocDrv = docBase: stdenv.mkDerivation ({
+    name = "rust_${pname}-${version}-doc";
+    builder = ./builder-doc.sh;
+    inherit propagatedBuildInputs builderCommon buildDrv features procMacro docBase;
+
+    dependencies = libDeps;
+  } // commonArgs);
+
+  # Doctests are compiled against the library, with dev-dependencies available.
+  doctestDrv = stdenv.mkDerivation ({
+    name = "rust_${pname}-${version}-doctest";
+    builder = ./builder-doc.sh;
+    inherit propagatedBuildInputs builderCommon buildDrv features procMacro;
+    doctest = true;
+
+    libOutDrv = libDrv.out;
+    libDevDrv = libDrv.dev;
+
+    buildInputs = toDevDrvs (dependencies ++ devDependencies);
+
+    dependencies = libDeps;
+    devDependencies = devDeps;
+  } // commonArgs);
+
 in
   libDrv // {
+    inherit rustc;
     build = buildDrv;
     bin = binDrv;
+    tests = testDrv;
+    check = checkDrv;
+    examples = mkTargetsDrv "example";
+    benches = mkTargetsDrv "bench";
+    doc = mkDocDrv null;
+    mkDoc = mkDocDrv;
+    doctest = doctestDrv;
   }
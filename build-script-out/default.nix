@@ -0,0 +1,56 @@
+{ stdenv, rustc }:
+let
+  manifest = builtins.fromTOML (builtins.readFile ./Cargo.toml);
+in stdenv.mkDerivation {
+  pname = manifest.package.name;
+  version = manifest.package.version;
+
+  dontUnpack = true;
+
+  nativeBuildInputs = [ rustc ];
+
+  buildPhase = ''
+    buildFlagsArray+=(
+      --color=always
+      -C codegen-units=1
+      -C opt-level=3
+    )
+
+    run() {
+      echo "rustc $* ''${buildFlagsArray[*]}"
+      rustc "$@" "''${buildFlagsArray[@]}"
+    }
+
+    run ${./src}/main.rs --crate-name build_script_out --crate-type bin -o build-script-out
+    run ${./src}/main.rs --crate-name build_script_out --test -o build-script-out-test
+  '';
+
+  testStdout = ''
+    Hello
+    cargo:rustc-link-lib=foo
+    cargo:rustc-cfg=has_foo
+    cargo:include=/foo/include
+    cargo::rustc-link-arg-bin=foo=-Wl,--defsym=a=1
+    cargo::metadata=version=1.0
+  '';
+
+  doCheck = true;
+  checkPhase = ''
+    ./build-script-out-test
+
+    ./build-script-out --out-dir out --links foo-sys --rust-version 1.77 <<<"$testStdout"
+    [[ "$(cat out/rustc-flags)" == $'--cfg=has_foo\n-lfoo' ]]
+    [[ "$(cat out/rustc-link-args-bin-foo)" == "-Clink-arg=-Wl,--defsym=a=1" ]]
+    [[ "$(cat out/links-metadata)" == $'export DEP_FOO_SYS_INCLUDE=/foo/include\nexport DEP_FOO_SYS_VERSION=1.0' ]]
+
+    rc=0
+    ./build-script-out --out-dir out2 --rust-version 1.70 <<<"$testStdout" || rc=$?
+    echo "Exit code for new syntax with old MSRV: $rc"
+    [[ $rc == 1 ]]
+  '';
+
+  installPhase = ''
+    mkdir -p $out/bin
+    cp -t $out/bin ./build-script-out
+  '';
+}
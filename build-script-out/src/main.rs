@@ -0,0 +1,164 @@
+use std::env;
+use std::fs;
+use std::io::{self, stdin, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+mod parse;
+
+use parse::{BuildOutput, LinkArgTarget, Version};
+
+const USAGE: &str = "\
+Usage: build-script-out --out-dir <dir> [--links <name>] [--rust-version <version>] [<build-stdout>]
+
+Parse the stdout of a build script from <build-stdout> (default to stdin), validate the
+directives, and write flags for builders into `rustc-*` and `links-metadata` files under <dir>.
+
+Options:
+    --out-dir <dir>             The `rust-support` directory to write files into.
+    --links <name>              `package.links` of the package, to propagate metadata.
+    --rust-version <version>    `package.rust-version` of the package, which decides whether
+                                the `cargo::` syntax is allowed.
+";
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut out_dir = None;
+    let mut links = None;
+    let mut rust_version = None;
+    let mut input = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("Missing argument for `{}`", arg))
+        };
+        match &*arg {
+            "--out-dir" => out_dir = Some(PathBuf::from(value()?)),
+            // Builders always pass these, which may be empty.
+            "--links" => links = value().ok(),
+            "--rust-version" => rust_version = value().ok(),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            _ if !arg.starts_with('-') && input.is_none() => input = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument: {}\n\n{}", arg, USAGE)),
+        }
+    }
+    let out_dir = out_dir.ok_or_else(|| format!("Missing `--out-dir`\n\n{}", USAGE))?;
+    let rust_version = match rust_version {
+        Some(ver) => Some(Version::parse(&ver)?),
+        None => None,
+    };
+
+    let mut stdout = String::new();
+    match &input {
+        Some(path) => {
+            stdout =
+                fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?
+        }
+        None => {
+            stdin()
+                .read_to_string(&mut stdout)
+                .map_err(|err| format!("<stdin>: {}", err))?;
+        }
+    }
+
+    let output = parse::parse(&stdout, rust_version)?;
+    for msg in &output.warnings {
+        eprintln!("\x1b[0;1;33mWarning\x1b[0m: {}", msg);
+    }
+    if links.is_none() {
+        for (key, value) in &output.metadata {
+            eprintln!(
+                "\x1b[0;1;33mWarning\x1b[0m: no 'links' defined in Cargo.toml, ignoring metadata {}={}",
+                key, value,
+            );
+        }
+    }
+    // Errors fail the build after all outputs are processed, as cargo does.
+    if !output.errors.is_empty() {
+        for msg in &output.errors {
+            eprintln!("\x1b[0;1;31mError\x1b[0m: {}", msg);
+        }
+        return Err("Build script reported errors".into());
+    }
+
+    write_files(&output, &out_dir, links.as_ref().map(|s| &**s))
+        .map_err(|err| format!("{}: {}", out_dir.display(), err))
+}
+
+/// Write lines into `<dir>/<name>`, or do nothing if there is none.
+/// Lines are sorted for reproducibility, unless the order matters.
+fn write_lines(dir: &Path, name: &str, mut lines: Vec<String>, sort: bool) -> io::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if sort {
+        lines.sort();
+    }
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(name))?;
+    for line in lines {
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn write_files(output: &BuildOutput, dir: &Path, links: Option<&str>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    write_lines(dir, "rustc-flags", output.rustc_flags.clone(), true)?;
+    // `check_cfgs` are only validated. Builders don't enable `unexpected_cfgs` checking, which
+    // needs `--check-cfg` of all features as well, so they are not written.
+    let env = output
+        .env
+        .iter()
+        .map(|(k, v)| format!("export {}={}", k, shell_quote(v)));
+    write_lines(dir, "rustc-env", env.collect(), true)?;
+
+    // The order of link args matters.
+    for (target, arg) in &output.link_args {
+        let name = match target {
+            LinkArgTarget::All => "rustc-link-args".to_owned(),
+            LinkArgTarget::Cdylib => "rustc-cdylib-flags".to_owned(),
+            LinkArgTarget::Bins => "rustc-link-args-bins".to_owned(),
+            LinkArgTarget::Bin(bin) => format!("rustc-link-args-bin-{}", bin),
+            LinkArgTarget::Tests => "rustc-link-args-tests".to_owned(),
+            LinkArgTarget::Examples => "rustc-link-args-examples".to_owned(),
+            LinkArgTarget::Benches => "rustc-link-args-benches".to_owned(),
+        };
+        write_lines(dir, &name, vec![format!("-Clink-arg={}", arg)], false)?;
+    }
+
+    if let Some(links) = links {
+        let metadata = output.metadata.iter().map(|(k, v)| {
+            let name = format!("DEP_{}_{}", links, k)
+                .to_uppercase()
+                .replace('-', "_");
+            format!("export {}={}", shell_quote(&name), shell_quote(v))
+        });
+        write_lines(dir, "links-metadata", metadata.collect(), true)?;
+    }
+    Ok(())
+}
+
+/// Quote a string for bash.
+fn shell_quote(s: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=.,/:@%".contains(c);
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s.to_owned()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
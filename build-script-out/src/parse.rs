@@ -0,0 +1,489 @@
+//! Parser of build script outputs.
+//!
+//! See:
+//! https://doc.rust-lang.org/cargo/reference/build-scripts.html#outputs-of-the-build-script
+//! https://github.com/rust-lang/cargo/blob/rust-1.80.0/src/cargo/core/compiler/custom_build.rs
+use std::fmt;
+
+/// The `cargo::` syntax is only allowed if the MSRV of the package is unset or at least this.
+const NEW_SYNTAX_MSRV: Version = Version(1, 77, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u64, pub u64, pub u64);
+
+impl Version {
+    /// Parse `package.rust-version`, which is `major.minor[.patch]`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid rust-version: {:?}", s);
+        let parts = s
+            .split('.')
+            .map(|x| x.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts.len() {
+            2 => Ok(Version(parts[0], parts[1], 0)),
+            3 => Ok(Version(parts[0], parts[1], parts[2])),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Targets which `rustc-link-arg*` apply to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkArgTarget {
+    All,
+    Cdylib,
+    Bins,
+    Bin(String),
+    Tests,
+    Examples,
+    Benches,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildOutput {
+    /// `-l`, `-L` and `--cfg` flags for all targets.
+    pub rustc_flags: Vec<String>,
+    pub check_cfgs: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub link_args: Vec<(LinkArgTarget, String)>,
+    pub metadata: Vec<(String, String)>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Parse the stdout of a build script.
+///
+/// `rust_version` is `package.rust-version` of the package, which decides whether
+/// the `cargo::` syntax is allowed.
+pub fn parse(stdout: &str, rust_version: Option<Version>) -> Result<BuildOutput, String> {
+    let mut out = BuildOutput::default();
+    for line in stdout.lines() {
+        let (data, new_syntax) = if line.starts_with("cargo::") {
+            match rust_version {
+                Some(ver) if ver < NEW_SYNTAX_MSRV => {
+                    return Err(format!(
+                        "The `cargo::` syntax for build script output instructions was added in Rust {}, \
+                         but the minimum supported Rust version of the package is {}: {}",
+                        NEW_SYNTAX_MSRV, ver, line,
+                    ));
+                }
+                _ => {}
+            }
+            (&line["cargo::".len()..], true)
+        } else if line.starts_with("cargo:") {
+            (&line["cargo:".len()..], false)
+        } else {
+            // Normal outputs.
+            continue;
+        };
+
+        let (key, value) = match split_once(data, '=') {
+            Some((key, value)) => (key, value.trim_end()),
+            None => {
+                return Err(format!(
+                    "Invalid output of build script: {}\nExpect a line with `{}KEY=VALUE`",
+                    line,
+                    if new_syntax { "cargo::" } else { "cargo:" },
+                ))
+            }
+        };
+
+        parse_directive(&mut out, line, key, value, new_syntax)?;
+    }
+    Ok(out)
+}
+
+fn parse_directive(
+    out: &mut BuildOutput,
+    line: &str,
+    key: &str,
+    value: &str,
+    new_syntax: bool,
+) -> Result<(), String> {
+    let expect_kv = |what: &str| match split_once(value, '=') {
+        Some(kv) => Ok(kv),
+        None => Err(format!(
+            "Invalid output of build script: {}\nExpect `{}`",
+            line, what
+        )),
+    };
+    let non_empty = |value: &str| {
+        if value.is_empty() {
+            Err(format!("Empty link path: {}", line))
+        } else {
+            Ok(())
+        }
+    };
+
+    match key {
+        // Ignored due to the sandbox.
+        "rerun-if-changed" | "rerun-if-env-changed" => {}
+        "rustc-link-arg" => out.link_args.push((LinkArgTarget::All, value.to_owned())),
+        "rustc-link-arg-cdylib" | "rustc-cdylib-link-arg" => out
+            .link_args
+            .push((LinkArgTarget::Cdylib, value.to_owned())),
+        "rustc-link-arg-bins" => out.link_args.push((LinkArgTarget::Bins, value.to_owned())),
+        "rustc-link-arg-bin" => {
+            let (bin, arg) = expect_kv("cargo::rustc-link-arg-bin=BIN=ARG")?;
+            if bin.is_empty() {
+                return Err(format!("Missing binary name: {}", line));
+            }
+            out.link_args
+                .push((LinkArgTarget::Bin(bin.to_owned()), arg.to_owned()));
+        }
+        "rustc-link-arg-tests" => out.link_args.push((LinkArgTarget::Tests, value.to_owned())),
+        "rustc-link-arg-examples" => out
+            .link_args
+            .push((LinkArgTarget::Examples, value.to_owned())),
+        "rustc-link-arg-benches" => out
+            .link_args
+            .push((LinkArgTarget::Benches, value.to_owned())),
+        "rustc-link-lib" => {
+            non_empty(value)?;
+            out.rustc_flags.push(format!("-l{}", value));
+        }
+        "rustc-link-search" => {
+            non_empty(value)?;
+            out.rustc_flags.push(format!("-L{}", value));
+        }
+        "rustc-flags" => {
+            let mut flags = value.split_whitespace();
+            while let Some(flag) = flags.next() {
+                let (kind, path) = if flag == "-l" || flag == "-L" {
+                    (flag, flags.next().unwrap_or(""))
+                } else if flag.starts_with("-l") || flag.starts_with("-L") {
+                    flag.split_at(2)
+                } else {
+                    return Err(format!(
+                        "Only -l and -L are allowed from build script: {}",
+                        line
+                    ));
+                };
+                non_empty(path)?;
+                out.rustc_flags.push(format!("{}{}", kind, path));
+            }
+        }
+        "rustc-cfg" => out.rustc_flags.push(format!("--cfg={}", value)),
+        "rustc-check-cfg" => {
+            if !is_check_cfg(value) {
+                return Err(format!(
+                    "Invalid check-cfg specification {:?}: {}",
+                    value, line
+                ));
+            }
+            out.check_cfgs.push(value.to_owned());
+        }
+        "rustc-env" => {
+            let (name, value) = expect_kv("cargo::rustc-env=VAR=VALUE")?;
+            if !is_env_name(name) {
+                return Err(format!(
+                    "Invalid environment variable name {:?}: {}",
+                    name, line
+                ));
+            }
+            out.env.push((name.to_owned(), value.to_owned()));
+        }
+        "warning" => out.warnings.push(value.to_owned()),
+        "error" if new_syntax => out.errors.push(value.to_owned()),
+        "metadata" if new_syntax => {
+            let (key, value) = expect_kv("cargo::metadata=KEY=VALUE")?;
+            out.metadata.push((key.to_owned(), value.to_owned()));
+        }
+        // Old `cargo:KEY=VALUE` lines with other keys are metadata.
+        _ if !new_syntax => out.metadata.push((key.to_owned(), value.to_owned())),
+        _ => {
+            return Err(format!(
+                "Unknown key `{}` of build script output: {}",
+                key, line
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn split_once(s: &str, sep: char) -> Option<(&str, &str)> {
+    s.find(sep)
+        .map(|idx| (&s[..idx], &s[idx + sep.len_utf8()..]))
+}
+
+fn is_env_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check the syntax of `cfg(name, ..., values("value", ..., none()))` for `rustc-check-cfg`.
+/// See: https://doc.rust-lang.org/rustc/check-cfg.html
+fn is_check_cfg(spec: &str) -> bool {
+    fn check_cfg(cur: &mut Cursor) -> Option<()> {
+        if cur.ident()? != "cfg" {
+            return None;
+        }
+        cur.expect('(')?;
+        let mut has_values = false;
+        cur.list(|cur| {
+            // `values(...)` must be the last one.
+            if has_values {
+                return None;
+            }
+            match cur.ident()? {
+                "any" if cur.eat('(') => cur.expect(')'),
+                "values" if cur.eat('(') => {
+                    has_values = true;
+                    cur.list(|cur| {
+                        if cur.string().is_some() {
+                            return Some(());
+                        }
+                        match cur.ident()? {
+                            "none" | "any" => {
+                                cur.expect('(')?;
+                                cur.expect(')')
+                            }
+                            _ => None,
+                        }
+                    })
+                }
+                _ => Some(()),
+            }
+        })
+    }
+
+    let mut cur = Cursor(spec);
+    check_cfg(&mut cur).is_some() && cur.0.trim().is_empty()
+}
+
+/// A minimal tokenizer for `is_check_cfg`. Whitespaces between tokens are skipped.
+struct Cursor<'a>(&'a str);
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        self.0 = self.0.trim_start();
+        if self.0.starts_with(c) {
+            self.0 = &self.0[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        if self.eat(c) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.0 = self.0.trim_start();
+        let end = self
+            .0
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.0.len());
+        let ident = &self.0[..end];
+        if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        self.0 = &self.0[end..];
+        Some(ident)
+    }
+
+    fn string(&mut self) -> Option<()> {
+        if !self.eat('"') {
+            return None;
+        }
+        let mut chars = self.0.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.0 = &self.0[i + 1..];
+                    return Some(());
+                }
+                '\\' => {
+                    chars.next()?;
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Parse comma separated items until `)`, with an optional trailing comma.
+    /// The opening `(` should be already eaten.
+    fn list(&mut self, mut item: impl FnMut(&mut Self) -> Option<()>) -> Option<()> {
+        loop {
+            if self.eat(')') {
+                return Some(());
+            }
+            item(self)?;
+            if !self.eat(',') {
+                return self.expect(')');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn kv(k: &str, v: &str) -> (String, String) {
+        (k.to_owned(), v.to_owned())
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(Version::parse("1.77").unwrap(), Version(1, 77, 0));
+        assert_eq!(Version::parse("1.80.1").unwrap(), Version(1, 80, 1));
+        assert!(Version::parse("1").is_err());
+        assert!(Version::parse("1.x").is_err());
+        assert!(Version(1, 76, 9) < NEW_SYNTAX_MSRV);
+    }
+
+    #[test]
+    fn test_old_syntax() {
+        let stdout = "\
+hello
+cargo:rerun-if-changed=build.rs
+cargo:rustc-link-lib=static=foo
+cargo:rustc-link-search=native=/foo
+cargo:rustc-flags=-l bar -L/bar
+cargo:rustc-cfg=has_foo
+cargo:rustc-env=FOO=a=b
+cargo:rustc-link-arg=-Wl,-z,now
+cargo:rustc-link-arg-bin=foo-bin=-Wl,--defsym=a=1
+cargo:rustc-cdylib-link-arg=-Wl,-soname,foo
+cargo:warning=be careful
+cargo:include=/foo/include
+cargo:error=not an error
+cargo:rustc-foo=bar
+cargo:warningfoo=baz
+";
+        let got = parse(stdout, Some(Version(1, 36, 0))).unwrap();
+        assert_eq!(
+            got,
+            BuildOutput {
+                rustc_flags: vec![
+                    s("-lstatic=foo"),
+                    s("-Lnative=/foo"),
+                    s("-lbar"),
+                    s("-L/bar"),
+                    s("--cfg=has_foo"),
+                ],
+                check_cfgs: vec![],
+                env: vec![kv("FOO", "a=b")],
+                link_args: vec![
+                    (LinkArgTarget::All, s("-Wl,-z,now")),
+                    (LinkArgTarget::Bin(s("foo-bin")), s("-Wl,--defsym=a=1")),
+                    (LinkArgTarget::Cdylib, s("-Wl,-soname,foo")),
+                ],
+                metadata: vec![
+                    kv("include", "/foo/include"),
+                    kv("error", "not an error"),
+                    kv("rustc-foo", "bar"),
+                    kv("warningfoo", "baz"),
+                ],
+                warnings: vec![s("be careful")],
+                errors: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_new_syntax() {
+        let stdout = "\
+cargo::rustc-check-cfg=cfg(has_foo)
+cargo::rustc-cfg=has_foo
+cargo::metadata=include=/foo/include
+cargo::rustc-link-arg-tests=-Wl,--defsym=b=2
+cargo::error=something is wrong
+";
+        for &msrv in &[None, Some(NEW_SYNTAX_MSRV)] {
+            let got = parse(stdout, msrv).unwrap();
+            assert_eq!(
+                got,
+                BuildOutput {
+                    rustc_flags: vec![s("--cfg=has_foo")],
+                    check_cfgs: vec![s("cfg(has_foo)")],
+                    link_args: vec![(LinkArgTarget::Tests, s("-Wl,--defsym=b=2"))],
+                    metadata: vec![kv("include", "/foo/include")],
+                    errors: vec![s("something is wrong")],
+                    ..BuildOutput::default()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let msrv = Some(Version(1, 80, 0));
+        let invalid = [
+            // Missing `=`.
+            "cargo:rustc-cfg",
+            // Unknown keys of the new syntax.
+            "cargo::rustc-foo=bar",
+            "cargo::include=/foo/include",
+            // Malformed values.
+            "cargo::metadata=include",
+            "cargo:rustc-env=FOO",
+            "cargo:rustc-env=A B=c",
+            "cargo:rustc-link-arg-bin=-Wl,-z,now",
+            "cargo:rustc-link-lib=",
+            "cargo:rustc-flags=-l",
+            "cargo:rustc-flags=-Clto",
+            "cargo::rustc-check-cfg=has_foo",
+        ];
+        for line in &invalid {
+            assert!(parse(line, msrv).is_err(), "should fail: {}", line);
+        }
+
+        // The `cargo::` syntax requires a new enough MSRV.
+        let err = parse("cargo::rustc-cfg=foo", Some(Version(1, 76, 0))).unwrap_err();
+        assert!(err.contains("1.77.0"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_cfg() {
+        let valid = [
+            "cfg()",
+            "cfg(has_foo)",
+            "cfg(any())",
+            "cfg(foo, bar_2, values())",
+            " cfg( foo , values(\"a\", \"b,c)\", \"\\\"\", none(),) ) ",
+            "cfg(feature, values(any()))",
+        ];
+        for spec in &valid {
+            assert!(is_check_cfg(spec), "should be valid: {}", spec);
+        }
+
+        let invalid = [
+            "",
+            "has_foo",
+            "cfg(foo",
+            "cfg(foo) bar",
+            "cfg(\"foo\")",
+            "cfg(1foo)",
+            "cfg(foo,, bar)",
+            "cfg(values(\"a\"), foo)",
+            "cfg(foo, values(a))",
+            "cfg(foo, values(\"a))",
+            "cfg(foo, values(none))",
+        ];
+        for spec in &invalid {
+            assert!(!is_check_cfg(spec), "should be invalid: {}", spec);
+        }
+    }
+}
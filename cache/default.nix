@@ -1,8 +1,13 @@
-{ writeText, mkRustPackageOrWorkspace }:
+{ lib, writeText, mkRustPackageOrWorkspace }:
 let
+  inherit (lib) attrValues concatMap;
+
   ws = mkRustPackageOrWorkspace {
     src = ./.;
   };
+
+  # The main package and feature variants under `variants` are built as separate roots.
+  dependenciesOf = profile: concatMap (pkg: pkg.dependencies) (attrValues ws.${profile});
 in
 writeText "cache-paths"
-  (toString (ws.dev.cache.dependencies ++ ws.release.cache.dependencies))
+  (toString (dependenciesOf "dev" ++ dependenciesOf "release"))
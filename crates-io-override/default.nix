@@ -1,13 +1,30 @@
 { lib, pkgs }:
 let
-  inherit (lib) optionalAttrs listToAttrs;
-  inherit (builtins) compareVersions;
+  inherit (lib) optionalAttrs listToAttrs any;
+  inherit (builtins) elemAt isString;
+  inherit (import ../lib/semver.nix { inherit lib; }) compareSemver;
+
+  # Entries are either names, or `{ name; ranges; }` for crates switching kinds among versions,
+  # with `[ from until ]` ranges where `null` means unbounded.
+  # Maintained by `noc maint proc-macros`. Versions are compared by semver, so pre-releases are
+  # before their releases.
+  inVersionRange = version: range:
+    let
+      from = elemAt range 0;
+      until = elemAt range 1;
+    in
+      (from == null || compareSemver version from >= 0) &&
+      (until == null || compareSemver version until < 0);
   procMacroOverrides =
     listToAttrs
-      (map (name: {
-        inherit name;
-        value = _: { procMacro = true; };
-      }) (import ./proc-macro.nix));
+      (map (entry:
+        if isString entry then {
+          name = entry;
+          value = _: { procMacro = true; };
+        } else {
+          inherit (entry) name;
+          value = { version, ... }: { procMacro = any (inVersionRange version) entry.ranges; };
+        }) (import ./proc-macro.nix));
 in
 with pkgs;
 procMacroOverrides //
@@ -21,8 +38,4 @@ procMacroOverrides //
     nativeBuildInputs = [ pkg-config ];
     propagatedBuildInputs = [ openssl ];
   };
-
-  proc-macro-hack = { version, ... }: {
-    procMacro = compareVersions version "0.5.0" >= 0;
-  };
 }
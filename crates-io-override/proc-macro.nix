@@ -2870,6 +2870,7 @@
 "proc-concat-bytes-impl"
 "proc-lock-macro"
 "proc-macro-error-attr"
+{ name = "proc-macro-hack"; ranges = [ [ "0.5.0" null ] ]; }
 "proc-macro-hack-impl"
 "proc-macro-id"
 "proc-macro-kwargs-derive"
@@ -20,13 +20,13 @@
     in flake-utils.lib.eachSystem supportedSystems (system:
       let
         pkgs = nixpkgs.legacyPackages.${system};
+        cratesIoOverrides = import ./crates-io-override {
+          inherit (nixpkgs) lib;
+          inherit pkgs;
+        };
         defaultRegistries = {
           "https://github.com/rust-lang/crates.io-index" =
-            nocargo-lib.pkg-info.mkIndex pkgs.fetchurl registry-crates-io
-            (import ./crates-io-override {
-              inherit (nixpkgs) lib;
-              inherit pkgs;
-            });
+            nocargo-lib.pkg-info.mkIndex pkgs.fetchurl registry-crates-io cratesIoOverrides;
         };
       in rec {
         apps.default = {
@@ -37,8 +37,11 @@
         # Is there a better place? `naersk` places builders under `lib.${system}`.
         lib = rec {
           mkIndex = nocargo-lib.pkg-info.mkIndex pkgs.fetchurl;
+          inherit (nocargo-lib.pkg-info) mkVendorDir;
+          # Overrides of crates.io packages, for `mkIndex` on mirrors or vendored registries.
+          inherit cratesIoOverrides;
           buildRustCrate = pkgs.callPackage ./build-rust-crate {
-            inherit (packages) toml2json;
+            inherit (packages) toml2json build-script-out;
             inherit nocargo-lib;
           };
           mkRustPackageOrWorkspace = pkgs.callPackage nocargo-lib.support.mkRustPackageOrWorkspace {
@@ -49,6 +52,7 @@
         packages = rec {
           default = noc;
           toml2json = pkgs.callPackage ./toml2json { };
+          build-script-out = pkgs.callPackage ./build-script-out { };
           noc = (lib.mkRustPackageOrWorkspace {
             src = ./noc;
           }).release.nocargo.bin;
@@ -106,11 +110,19 @@
             _0004-platform-cfg = target-cfg.platform-cfg-tests;
             _0005-glob = glob.glob-tests;
             _0006-sanitize-relative-path = support.sanitize-relative-path-tests;
+            _0007-resolver-from-manifest = support.resolver-from-manifest-tests;
+            _0008-rustflags-from-config = support.rustflags-from-config-tests;
+            _0009-cargo-config = support.cargo-config-tests;
+            _0010-rust-version = support.rust-version-tests;
 
             _0100-pkg-info-from-toml = pkg-info.pkg-info-from-toml-tests;
             _0101-preprocess-feature = resolve.preprocess-feature-tests;
             _0102-update-feature = resolve.update-feature-tests;
             _0103-resolve-feature = resolve.resolve-feature-tests;
+            _0104-resolve-feature-resolver = resolve.resolve-feature-resolver-tests;
+            _0105-vendor-dir = pkg-info.vendor-dir-tests;
+            _0106-vendored-registry = pkg-info.vendored-registry-tests;
+            _0107-proc-macro-override = pkg-info.proc-macro-override-tests;
 
             _0200-resolve-deps = resolve.resolve-deps-tests;
             _0201-build-from-src-dry = support.build-from-src-dry-tests;
@@ -1,9 +1,11 @@
 { lib, ... }:
 let
-  inherit (builtins) readFile readDir fromJSON fromTOML toString attrNames match;
+  inherit (builtins) readFile readDir fromJSON fromTOML toString attrNames match hashFile;
+  inherit (builtins) intersectAttrs;
   inherit (lib)
-    stringLength splitString replaceStrings substring isString toLower
-    filter listToAttrs mapAttrs mapAttrsToList optionalAttrs warnIf;
+    stringLength splitString replaceStrings substring concatStrings concatStringsSep isString isAttrs toLower
+    filter foldl' listToAttrs mapAttrs mapAttrsToList filterAttrs optionalAttrs warnIf
+    attrByPath setAttrByPath recursiveUpdate;
 in
 rec {
   toPkgId = { name, version, source ? null, ... }:
@@ -38,23 +40,60 @@ rec {
   in
     go path // { __registry_index = true; };
 
-  # Get pkg info of the given package, with overrides applied if exists.
-  getPkgInfoFromIndex = index: { name, version, checksum ? null, ... }: let
+  # Make a registry from a directory vendored by `cargo vendor`, in the same layout as `mkIndex`.
+  # Sources are verified against `.cargo-checksum.json` when used.
+  # See: https://doc.rust-lang.org/cargo/reference/source-replacement.html#directory-sources
+  mkVendorDir = path: overrides: let
+    mkPkgInfo = dir: let
+      root = path + "/${dir}";
+      manifest = fromTOML (readFile (root + "/Cargo.toml"));
+      checksum = fromJSON (readFile (root + "/.cargo-checksum.json"));
+      mismatched = filter
+        (file: hashFile "sha256" (root + "/${file}") != checksum.files.${file})
+        (attrNames checksum.files);
+      info = mkPkgInfoFromCargoToml manifest root;
+      override = overrides.${info.name} or null;
+    in
+      info // {
+        # Git crates have no checksum.
+        sha256 = checksum.package or null;
+        yanked = false;
+        src =
+          if mismatched == [] then root
+          else throw "Files of vendored crate ${info.name} ${info.version} are modified: ${toString mismatched}";
+      } // optionalAttrs (override != null) {
+        # Unlike registries, proc macro crates are known from Cargo.toml.
+        __override = override;
+      };
+
+    crates = attrNames (filterAttrs (_: type: type == "directory") (readDir path));
+  in
+    foldl' (index: dir: let info = mkPkgInfo dir; in
+      recursiveUpdate index (setAttrByPath (indexPathOf info.name ++ [ info.version ]) info)
+    ) { __registry_index = true; } crates;
+
+  # The path of a crate in the index.
+  # https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
+  indexPathOf = name: let
     name' = toLower name;
     len = stringLength name';
-    crate =
-      if len == 1 then
-        index."1".${name'} or null
-      else if len == 2 then
-        index."2".${name'} or null
-      else if len == 3 then
-        index."3".${substring 0 1 name'}.${name'} or null
-      else
-        index.${substring 0 2 name'}.${substring 2 2 name'}.${name'} or null;
+  in
+    if len == 1 then
+      [ "1" name' ]
+    else if len == 2 then
+      [ "2" name' ]
+    else if len == 3 then
+      [ "3" (substring 0 1 name') name' ]
+    else
+      [ (substring 0 2 name') (substring 2 2 name') name' ];
+
+  # Get pkg info of the given package, with overrides applied if exists.
+  getPkgInfoFromIndex = index: { name, version, checksum ? null, ... }: let
+    crate = attrByPath (indexPathOf name) null index;
     info = crate.${version} or null;
   in
     if !(index ? __registry_index) then
-      throw "Invalid registry. Do you forget `mkIndex` on registry paths?"
+      throw "Invalid registry. Do you forget `mkIndex` or `mkVendorDir` on registry paths?"
     else if crate == null then
       throw "Package ${name} is not found in index"
     else if info == null then
@@ -67,12 +106,15 @@ rec {
   # Make a set of pkg infos keyed by version.
   mkPkgInfoSet = mkSrc: name: content: override: let
     lines = filter (line: line != "") (splitString "\n" content);
-    parseLine = line: let parsed = fromJSON line; in {
+    parseLine = line: let
+      parsed = fromJSON line;
+      info = mkPkgInfoFromRegistry mkSrc parsed;
+    in {
       name = parsed.vers;
-      value = mkPkgInfoFromRegistry mkSrc parsed
+      value = info
         // optionalAttrs (override != null) {
           # Proc macro crates behave differently in dependency resolution.
-          procMacro = (override { inherit (parsed) version; features = { }; }).procMacro or false;
+          procMacro = (override { version = parsed.vers; features = { }; }).procMacro or info.procMacro;
           __override = override;
         };
     };
@@ -88,6 +130,7 @@ rec {
   #   yanked = false;         # Whether it's yanked.
   #   links = "z";            # The native library to link. (null or string)
   #   procMacro = false;      # Whether this is a proc-macro library. See comments below.
+  #   rustVersion = "1.56";   # The minimal supported rustc version. (null or string)
   #   features = {            # Features provided.
   #     default = [ "std" ];
   #     std = [];
@@ -109,7 +152,7 @@ rec {
   mkPkgInfoFromRegistry =
     mkSrc:
     # https://github.com/rust-lang/cargo/blob/2f3df16921deb34a92700f4d5a7ecfb424739558/src/cargo/sources/registry/mod.rs#L259
-    { name, vers, deps, features, cksum, yanked ? false, links ? null, v ? 1, ... }:
+    { name, vers, deps, features, cksum, yanked ? false, links ? null, v ? 1, proc_macro ? false, rust_version ? null, ... }:
     if v != 1 then
       throw "${name} ${vers}: Registry layout version ${toString v} is too new to understand"
     else
@@ -119,8 +162,9 @@ rec {
       sha256 = cksum;
       dependencies = map sanitizeDep deps;
       # N.B. Proc macro indicator is not in the registry: https://github.com/rust-lang/cargo/issues/9605
-      # This would be overrided in `mkPkgInfoSet`.
-      procMacro = false;
+      # This would be overrided in `mkPkgInfoSet`. Only registries generated by `noc vendor` have it.
+      procMacro = proc_macro;
+      rustVersion = rust_version;
       src = mkSrc {
         inherit name;
         version = vers;
@@ -159,6 +203,59 @@ rec {
       rename = replaceStrings ["-"] ["_"] name;
     };
 
+  # Resolve `workspace = true` fields and dependencies of a member Cargo.toml,
+  # inheriting from `[workspace.package]` and `[workspace.dependencies]` of the workspace root Cargo.toml.
+  # `memberPath` is the path of the member relative to the workspace root.
+  # https://doc.rust-lang.org/cargo/reference/workspaces.html#the-package-table
+  # https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#inheriting-a-dependency-from-a-workspace
+  inheritWorkspaceManifest = workspaceManifest: memberPath: manifest: let
+    wsPackage = workspaceManifest.workspace.package or {};
+    wsDeps = workspaceManifest.workspace.dependencies or {};
+
+    isInherited = v: isAttrs v && v.workspace or false == true;
+
+    inheritField = field: v:
+      if isInherited v then
+        wsPackage.${field} or (throw "`package.${field}` is inherited but not defined in `[workspace.package]`")
+      else
+        v;
+
+    # `path` in `[workspace.dependencies]` is relative to the workspace root.
+    memberToRoot = concatStrings (map (_: "../") (filter (s: s != "" && s != ".") (splitString "/" memberPath)));
+
+    defaultFeaturesOf = v: v.default-features or v.default_features or null;
+
+    # Only `features` and `optional` can be specified along with `workspace = true`.
+    # `features` are additive, and `default-features = true` can re-enable defaults disabled by the workspace.
+    inheritDep = name: v: let
+      wsDep = wsDeps.${name} or (throw "Dependency `${name}` is inherited but not defined in `[workspace.dependencies]`");
+      wsDep' = if isString wsDep then { version = wsDep; } else wsDep;
+    in
+      if isInherited v then
+        removeAttrs wsDep' [ "default_features" ] // {
+          features = wsDep'.features or [] ++ v.features or [];
+          default-features = defaultFeaturesOf wsDep' != false || defaultFeaturesOf v == true;
+        } // optionalAttrs (v ? optional) {
+          inherit (v) optional;
+        } // optionalAttrs (wsDep' ? path) {
+          path = memberToRoot + wsDep'.path;
+        }
+      else
+        v;
+
+    inheritDepTables = set:
+      set // mapAttrs (_: mapAttrs inheritDep)
+        (intersectAttrs { dependencies = null; dev-dependencies = null; build-dependencies = null; } set);
+
+  in
+    inheritDepTables manifest
+    // optionalAttrs (manifest ? package) {
+      package = mapAttrs inheritField manifest.package;
+    }
+    // optionalAttrs (manifest ? target) {
+      target = mapAttrs (_: inheritDepTables) manifest.target;
+    };
+
   # Build a simplified crate into from a parsed Cargo.toml.
   mkPkgInfoFromCargoToml = { lockVersion ? 3, package, features ? {}, target ? {}, ... }@args: src: let
     transDeps = target: kind:
@@ -216,6 +313,7 @@ rec {
       inherit src features;
       links = package.links or null;
       procMacro = args.lib.proc-macro or false;
+      rustVersion = package.rust-version or null;
       dependencies =
         collectTargetDeps null args ++
         mapAttrsToList collectTargetDeps target;
@@ -233,6 +331,7 @@ rec {
         src = "<src>";
         links = null;
         procMacro = false;
+        rustVersion = null;
         dependencies = [
           {
             name = "tokio";
@@ -261,6 +360,7 @@ rec {
           src = "<src>";
           links = null;
           procMacro = false;
+          rustVersion = null;
           dependencies = [
             {
               name = "semver";
@@ -277,5 +377,108 @@ rec {
         };
       in
         assertEq info expected;
+
+    workspace-inheritance =
+      let
+        wsCargoToml = fromTOML (readFile ../tests/workspace-inheritance/Cargo.toml);
+        cargoToml = inheritWorkspaceManifest wsCargoToml "" wsCargoToml;
+        info = mkPkgInfoFromCargoToml cargoToml "<src>";
+        expected = {
+          name = "foo";
+          version = "0.2.0";
+          features = { };
+          src = "<src>";
+          links = null;
+          procMacro = false;
+          rustVersion = "1.64";
+          dependencies = [
+            {
+              name = "bar";
+              package = "bar";
+              default_features = false;
+              features = [ "hello" ];
+              kind = "normal";
+              optional = false;
+              req = null;
+              target = null;
+              source = null;
+            }
+          ];
+        };
+      in
+        assertEq info expected;
+
+    workspace-inheritance-dep =
+      let
+        wsCargoToml = {
+          workspace.dependencies.bar = { path = "crates/bar"; default_features = false; };
+        };
+        cargoToml = {
+          package = { name = "foo"; version = "0.1.0"; };
+          dependencies.bar = { workspace = true; optional = true; };
+        };
+        got = (inheritWorkspaceManifest wsCargoToml "crates/foo" cargoToml).dependencies.bar;
+      in
+        assertEq got {
+          path = "../../crates/bar";
+          features = [ ];
+          default-features = false;
+          optional = true;
+        };
+  };
+
+  vendor-dir-tests = { assertEq, ... }: let
+    vendor = ../tests/vendor-dir/vendor;
+    index = mkVendorDir vendor {
+      greeting = _: { buildInputs = [ "some-inputs" ]; };
+    };
+    info = getPkgInfoFromIndex index {
+      name = "greeting";
+      version = "0.1.0";
+      checksum = "37c50cfc0f3abd76c66100fedfe74ce85df4b09c575ba035b538c11c04599311";
+    };
+  in
+  {
+    info = assertEq
+      (removeAttrs info [ "__override" ])
+      {
+        name = "greeting";
+        version = "0.1.0";
+        features = { };
+        src = vendor + "/greeting";
+        sha256 = "37c50cfc0f3abd76c66100fedfe74ce85df4b09c575ba035b538c11c04599311";
+        yanked = false;
+        links = null;
+        procMacro = false;
+        rustVersion = null;
+        dependencies = [ ];
+      };
+    override = assertEq (info.__override {}).buildInputs [ "some-inputs" ];
+  };
+
+  # Registries generated by `noc vendor` record proc macro crates.
+  vendored-registry-tests = { assertEq, ... }: let
+    index = mkIndex (args: args) ../tests/vendored-registry {};
+    info = getPkgInfoFromIndex index { name = "foo"; version = "0.1.0"; };
+  in
+  {
+    proc-macro = assertEq info.procMacro true;
+    src = assertEq info.src.url "file:///vendored-registry/crates/foo/0.1.0/download";
   };
+
+  # Ranged entries of `crates-io-override` are evaluated against versions in the registry.
+  proc-macro-override-tests = { assertEq, pkgs, ... }: let
+    overrides = import ../crates-io-override { inherit lib pkgs; };
+    versions = [ "0.4.3" "0.5.0-rc.1" "0.5.0" "0.5.19" ];
+    content = concatStringsSep "\n" (map (vers:
+      ''{"name":"proc-macro-hack","vers":"${vers}","deps":[],"features":{},"cksum":"00"}''
+    ) versions);
+    infos = mkPkgInfoSet (args: args) "proc-macro-hack" content overrides.proc-macro-hack;
+  in
+    assertEq (mapAttrs (_: info: info.procMacro) infos) {
+      "0.4.3" = false;
+      "0.5.0-rc.1" = false;
+      "0.5.0" = true;
+      "0.5.19" = true;
+    };
 }
@@ -4,7 +4,7 @@ let
   inherit (lib)
     foldl' concatStringsSep listToAttrs filter elemAt length optional sort elem flatten
     hasPrefix substring
-    attrValues mapAttrs attrNames filterAttrs composeManyExtensions assertMsg;
+    attrValues mapAttrs mapAttrsToList attrNames filterAttrs composeManyExtensions assertMsg;
   inherit (self.semver) parseSemverReq;
   inherit (self.pkg-info) mkPkgInfoFromCargoToml toPkgId sanitizeDep;
 in rec {
@@ -169,15 +169,43 @@ in rec {
         enableFeatures pkgId defs (prev // { ${feat} = true; }) nexts
     ) prev features;
 
+  # Features of dependency `depName` enabled by `foo/bar` features in `features`.
+  # [String] -> [String]
+  nestedDepFeatures = depName: features:
+    filter (feat: feat != null)
+      (map (feat: let m = match "(.*)/(.*)" feat; in
+        if m != null && elemAt m 0 == depName then
+          elemAt m 1
+        else
+          null
+        ) features);
+
   # Resolve all features.
   # Note that dependent features like `foo/bar` are only available during resolution,
   # and will be removed in result set.
+  # Packages unreachable from the root have no features enabled.
   #
   # Returns:
   # {
   #   "libc 0.1.0 (https://...)" = [ "default" "foo" "bar" ];
   # }
-  resolveFeatures = {
+  resolveFeatures = args: mapAttrs (id: toFeatureList) (resolveFeaturesRaw args);
+
+  # Convert a result of `resolveFeaturesRaw` into a list, with dependent features removed.
+  toFeatureList = feats:
+    if feats == null then
+      [ ]
+    else
+      filter (feat: match ".*/.*" feat == null) (attrNames feats);
+
+  # Same as `resolveFeatures`, but returns the set of enabled features including dependent ones,
+  # or `null` for packages unreachable from the root.
+  #
+  # Returns:
+  # {
+  #   "libc 0.1.0 (https://...)" = { default = true; "foo/bar" = true; };
+  # }
+  resolveFeaturesRaw = {
   # Follows the layout of the output of `resolveDepsFromLock`.
     pkgSet
   # Dependency edges (`{ name, kind, resolved, ... }`) will be checked by this filter.
@@ -200,34 +228,29 @@ in rec {
           (filter (dep: dep.optional) dependencies))
     ) pkgSet;
 
-    # initialFeatures = mapAttrs (id: defs: mapAttrs (k: v: false) defs) featureDefs;
-    initialFeatures = mapAttrs (id: info: {}) pkgSet;
+    # `null` means the package is not reached yet.
+    initialFeatures = mapAttrs (id: info: null) pkgSet;
 
     # Overlay of spreading <id>'s nested features into dependencies and enable optional dependencies.
     updateDepsOverlay = id: final: prev: let
       info = pkgSet.${id};
-      finalFeatures = final.${id} or {};
+      finalFeatures = final.${id};
       updateDep = { name, optional, resolved, default_features, features, ... }: final: prev: let
         depFeatures =
           lib.optional (default_features && featureDefs.${resolved} ? default) "default" ++
           features ++
-          filter (feat: feat != null)
-            (map (feat: let m = match "(.*)/(.*)" feat; in
-              if m != null && elemAt m 0 == name then
-                elemAt m 1
-              else
-                null
-              ) (attrNames finalFeatures));
+          nestedDepFeatures name (attrNames finalFeatures);
       in
         {
           ${resolved} =
             # This condition must be evaluated under `${resolved} =`,
             # or we'll enter an infinite recursion.
-            if optional -> finalFeatures.${name} or false then
+            # Unreachable packages don't propagate their features.
+            if finalFeatures != null && (optional -> finalFeatures.${name} or false) then
               enableFeatures
                 resolved
                 featureDefs.${resolved}
-                prev.${resolved}
+                (if prev.${resolved} == null then {} else prev.${resolved})
                 depFeatures
             else
               prev.${resolved};
@@ -243,7 +266,7 @@ in rec {
       ${rootId} = enableFeatures
         rootId
         featureDefs.${rootId}
-        initialFeatures.${rootId}
+        {}
         rootFeatures;
     };
 
@@ -253,11 +276,82 @@ in rec {
       final
       initialFeatures;
 
-    final' =
-      mapAttrs (id: feats: filter (feat: match ".*/.*" feat == null) (attrNames feats)) final;
+  in
+    final;
+
+  # Resolve features of packages built for the target, and for the host (build dependencies,
+  # proc-macros and their dependencies), according to the feature resolver version.
+  # Dependencies in `pkgSet` should have `targetEnabled` telling whether they are enabled on the target.
+  # See: https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2
+  #
+  # Returns:
+  # {
+  #   target = { "libc 0.1.0 (https://...)" = [ "default" ]; };
+  #   host = { "libc 0.1.0 (https://...)" = [ "default" "std" ]; };
+  # }
+  resolveFeaturesWithResolver = {
+    pkgSet
+  , rootId
+  , rootFeatures
+  # "1", "2" or "3". Version 3 only differs from 2 in dependency resolution, which is done by cargo.
+  , resolver
+  # Whether dev-dependencies of the root are enabled, for building tests, examples and benches.
+  , withDev ? false
+  }: let
+    # Dev-dependencies of non-root packages are never built.
+    pkgSet' = pkgSet // {
+      ${rootId} = pkgSet.${rootId} // {
+        dependencies = map (dep: dep // { isRootDev = dep.kind == "dev"; }) pkgSet.${rootId}.dependencies;
+      };
+    };
+    isRootDev = dep: dep.isRootDev or false;
+    isProcMacro = dep: dep.resolved != null && pkgSet.${dep.resolved}.procMacro or false;
+
+    # V1 unifies features across all dependency kinds and targets.
+    unified = resolveFeatures {
+      pkgSet = pkgSet';
+      inherit rootId rootFeatures;
+      depFilter = dep: dep.kind != "dev" || isRootDev dep;
+    };
+
+    # V2 ignores dependencies of other targets and dev-dependencies not being built,
+    # and resolves features of packages built for the host separately.
+    isTargetDep = dep: dep.targetEnabled && (dep.kind == "normal" || withDev && isRootDev dep) && !isProcMacro dep;
+    isHostRootDep = dep: dep.targetEnabled && (dep.kind == "build" || (dep.kind == "normal" || withDev && isRootDev dep) && isProcMacro dep);
+
+    targetRaw = resolveFeaturesRaw {
+      pkgSet = pkgSet';
+      inherit rootId rootFeatures;
+      depFilter = isTargetDep;
+    };
+
+    # Build dependencies and proc-macros of all packages built for the target are roots of the host.
+    hostRootId = "<host>";
+    hostRootDeps = flatten (mapAttrsToList (id: features:
+      map (dep: dep // {
+        kind = "normal";
+        optional = false;
+        features = dep.features ++ nestedDepFeatures dep.name (attrNames features);
+      }) (filter (dep: isHostRootDep dep && (dep.optional -> features.${dep.name} or false))
+        pkgSet'.${id}.dependencies)
+    ) (filterAttrs (id: features: features != null) targetRaw));
+
+    host = removeAttrs (resolveFeatures {
+      pkgSet = pkgSet' // {
+        ${hostRootId} = { features = {}; dependencies = hostRootDeps; };
+      };
+      rootId = hostRootId;
+      rootFeatures = [];
+      depFilter = dep: dep.targetEnabled && (dep.kind == "normal" || dep.kind == "build");
+    }) [ hostRootId ];
 
   in
-    final';
+    if resolver == "1" then
+      { target = unified; host = unified; }
+    else if resolver == "2" || resolver == "3" then
+      { target = mapAttrs (id: toFeatureList) targetRaw; inherit host; }
+    else
+      throw "Unsupported resolver version: ${resolver}";
 
   preprocess-feature-tests = { assertEq, ... }: let
     test = optionalDeps: featureDefs: expect:
@@ -391,6 +485,78 @@ in rec {
       dep-id = [ "default" ];
       tokio-id = [ "fs" "sync" "macros" ];
     };
+
+    unreachable = test pkgSet2 "dep-id" [ ] {
+      my-id = [ ];
+      dep-id = [ ];
+      tokio-id = [ "sync" ];
+    };
+  };
+
+  resolve-feature-resolver-tests = { assertEq, ... }: let
+    test = resolver: withDev: expect: let
+      resolved = resolveFeaturesWithResolver {
+        inherit pkgSet resolver withDev;
+        rootId = "root";
+        rootFeatures = [ ];
+      };
+      sortFeatures = mapAttrs (id: feats: sort (a: b: a < b) feats);
+    in
+      assertEq resolved (mapAttrs (_: sortFeatures) expect);
+
+    dep = resolved: kind: features: {
+      name = resolved;
+      inherit resolved kind features;
+      optional = false;
+      default_features = false;
+      targetEnabled = true;
+    };
+
+    pkgSet = {
+      root = {
+        features = { };
+        procMacro = false;
+        dependencies = [
+          (dep "common" "normal" [ "normal" ])
+          (dep "common" "build" [ "build" ])
+          (dep "common" "dev" [ "dev" ])
+          (dep "common" "normal" [ "other-target" ] // { targetEnabled = false; })
+          (dep "macro" "normal" [ ])
+        ];
+      };
+      macro = {
+        features = { };
+        procMacro = true;
+        dependencies = [ (dep "common" "normal" [ "macro" ]) ];
+      };
+      common = {
+        features = { normal = [ ]; build = [ ]; dev = [ ]; other-target = [ ]; macro = [ ]; };
+        procMacro = false;
+        dependencies = [ ];
+      };
+    };
+
+    unified = {
+      root = [ ];
+      macro = [ ];
+      common = [ "normal" "build" "dev" "other-target" "macro" ];
+    };
+
+    v2Expect = {
+      target = { root = [ ]; macro = [ ]; common = [ "normal" ]; };
+      host = { root = [ ]; macro = [ ]; common = [ "build" "macro" ]; };
+    };
+
+  in {
+    v1 = test "1" false { target = unified; host = unified; };
+    v1-dev = test "1" true { target = unified; host = unified; };
+
+    # Dev-dependencies and other targets don't leak, and the host is resolved separately.
+    v2 = test "2" false v2Expect;
+    v2-dev = test "2" true (v2Expect // {
+      target = { root = [ ]; macro = [ ]; common = [ "normal" "dev" ]; };
+    });
+    v3 = test "3" false v2Expect;
   };
 
   resolve-deps-tests = { assertEq, defaultRegistries, ... }: {
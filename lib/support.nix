@@ -1,13 +1,14 @@
 { lib, self }:
 let
-  inherit (builtins) fromTOML toJSON match tryEval split;
+  inherit (builtins) fromTOML toJSON match tryEval split pathExists compareVersions;
   inherit (lib)
     readFile mapAttrs mapAttrs' makeOverridable warnIf
-    isString hasPrefix
-    filter flatten elem elemAt listToAttrs subtractLists concatStringsSep
-    attrNames attrValues recursiveUpdate optionalAttrs;
-  inherit (self.pkg-info) mkPkgInfoFromCargoToml getPkgInfoFromIndex toPkgId;
-  inherit (self.resolve) resolveDepsFromLock resolveFeatures;
+    isString isAttrs isList hasPrefix hasInfix
+    filter flatten elem elemAt head length listToAttrs subtractLists concatStringsSep concatMap foldl' toposort
+    genericClosure
+    attrNames attrValues filterAttrs recursiveUpdate optional optionalAttrs getVersion splitString;
+  inherit (self.pkg-info) mkPkgInfoFromCargoToml inheritWorkspaceManifest getPkgInfoFromIndex toPkgId;
+  inherit (self.resolve) resolveDepsFromLock resolveFeaturesWithResolver;
   inherit (self.target-cfg) platformToCfgs evalTargetCfgStr;
   inherit (self.glob) globMatchDir;
 in
@@ -53,12 +54,12 @@ rec {
       knownFields = [
         "name"
         "inherits"
-        # "package" # Unsupported yet.
+        "package"
         "build-override"
 
         "opt-level"
         "debug"
-        # split-debug-info # Unsupported.
+        "split-debuginfo"
         "strip"
         "debug-assertions"
         "overflow-checks"
@@ -77,21 +78,195 @@ rec {
 
     in profiles;
 
+  # The profile of a package, with `[profile.<name>.package.<spec>]` overrides applied.
+  # `forHost` is for build dependencies and proc-macros.
+  # The precedence is the package, `"*"` for non-members, `build-override` for the host, and then the profile.
+  # `build-override` of the result is for the build script of the package.
+  # See: https://doc.rust-lang.org/cargo/reference/profiles.html#overrides
+  profileForPkg = profile: { name, version, isLocalPkg ? false, ... }: forHost:
+    let
+      overrides = profile.package or {};
+      matched = filter (spec: overrides ? ${spec}) [ name "${name}@${version}" ];
+      forbidden = [ "package" "build-override" "panic" "lto" "rpath" ];
+      check = spec: o:
+        let bad = filter (k: o ? ${k}) forbidden; in
+        if bad != [] then
+          throw "Profile overrides for `${spec}` cannot set: ${toString bad}"
+        else
+          o;
+      pkgOverride =
+        if length matched > 1 then
+          throw "Multiple profile overrides match package ${name}@${version}: ${toString matched}"
+        else if matched != [] then
+          check (head matched) overrides.${head matched}
+        else
+          {};
+      allOverride = if isLocalPkg then {} else check "*" (overrides."*" or {});
+      applyOverrides = p: p // allOverride // pkgOverride;
+      buildOverride = profile.build-override or {};
+      base = removeAttrs profile [ "package" ];
+    in
+      applyOverrides (if forHost then base // buildOverride else base) // {
+        build-override = applyOverrides buildOverride;
+      };
+
+  # The feature resolver version, from the root manifest.
+  # See: https://doc.rust-lang.org/cargo/reference/resolver.html#resolver-versions
+  resolverFromManifest = manifest:
+    let
+      edition = manifest.package.edition or "2015";
+      # `edition.workspace = true`.
+      edition' = if isAttrs edition then manifest.workspace.package.edition or "2015" else edition;
+    in
+      manifest.workspace.resolver or manifest.package.resolver or (
+        if edition' >= "2024" then "3"
+        else if edition' >= "2021" then "2"
+        else "1");
+
+  # Extra rustc flags for all crates, from arguments and `.cargo/config.toml`.
+  # Like cargo, the first available one of these is used:
+  # 1. `rustflags`, like the `RUSTFLAGS` environment.
+  # 2. All `targetRustflags` and `[target.<triple-or-cfg>] rustflags` matching the host, joined together.
+  #    `targetRustflags` overrides the config for the same key.
+  # 3. `[build] rustflags`.
+  # `[target.<triple>] linker` is always respected.
+  # See: https://doc.rust-lang.org/cargo/reference/config.html#buildrustflags
+  rustflagsFromConfig = { config, rustflags, targetRustflags, hostTriple, hostCfgs, root }:
+    let
+      # Strings are space-separated flags.
+      toFlags = v: if isList v then v else filter (s: isString s && s != "") (split " +" v);
+
+      targets =
+        mapAttrs (key: t: toFlags t.rustflags) (filterAttrs (key: t: t ? rustflags) (config.target or {}))
+        // mapAttrs (key: toFlags) targetRustflags;
+      isMatched = key:
+        if hasPrefix "cfg(" key then evalTargetCfgStr hostCfgs key
+        else key == hostTriple;
+      matched = filter isMatched (attrNames targets);
+
+      linker = config.target.${hostTriple}.linker or null;
+      # Relative paths with slashes are relative to the project root.
+      linker' =
+        if hasInfix "/" linker && !hasPrefix "/" linker then "${root}/${linker}"
+        else linker;
+    in
+      (if rustflags != null then rustflags
+      else if matched != [] then concatMap (key: targets.${key}) matched
+      else toFlags (config.build.rustflags or []))
+      ++ optional (linker != null) "-Clinker=${linker'}";
+
+  # Load `.cargo/config.toml` of a directory, or `{}` if there is none.
+  # Like cargo, the legacy `.cargo/config` is preferred if both exist.
+  loadCargoConfig = dir:
+    if pathExists (dir + "/.cargo/config") then fromTOML (readFile (dir + "/.cargo/config"))
+    else if pathExists (dir + "/.cargo/config.toml") then fromTOML (readFile (dir + "/.cargo/config.toml"))
+    else {};
+
+  # Merge cargo configs, where latter ones take precedence. Arrays are concatenated.
+  # See: https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
+  mergeCargoConfigs = let
+    merge = lhs: rhs:
+      if isAttrs lhs && isAttrs rhs then
+        lhs // mapAttrs (k: v: if lhs ? ${k} then merge lhs.${k} v else v) rhs
+      else if isList lhs && isList rhs then
+        lhs ++ rhs
+      else
+        rhs;
+  in
+    foldl' merge {};
+
+  # Environments from `[env]`. Relative paths are relative to `root`.
+  # `force` makes no difference, since builders start with a clean environment
+  # and variables set by cargo are never overridden.
+  # See: https://doc.rust-lang.org/cargo/reference/config.html#env
+  envFromConfig = config: root:
+    mapAttrs (name: v:
+      if isString v then v
+      else if v.relative or false then "${root}/${v.value}"
+      else v.value
+    ) (config.env or {});
+
+  # Apply `[source.<name>] replace-with` to registries, which are keyed by URLs as in `Cargo.lock`.
+  # The replacing source is either a registry with an URL, or a directory or local registry
+  # which should be defined in `registries` with its name.
+  # See: https://doc.rust-lang.org/cargo/reference/source-replacement.html
+  registriesFromConfig = config: registries:
+    let
+      sources = config.source or {};
+      sourceUrl = name:
+        if name == "crates-io" then "https://github.com/rust-lang/crates.io-index"
+        else sources.${name}.registry or null;
+
+      # Follow the chain of replacements.
+      replacementOf = seen: name:
+        if elem name seen then
+          throw "Cyclic source replacement: ${concatStringsSep " -> " (seen ++ [ name ])}"
+        else if sources.${name} ? replace-with then
+          replacementOf (seen ++ [ name ]) sources.${name}.replace-with
+        else
+          name;
+
+      getRegistry = from: name: let url = sourceUrl name; in
+        if url != null then
+          registries.${url}
+            or (throw "Registry `${url}` replacing source `${from}` not found. Please define it in `extraRegistries`.")
+        else if !(sources ? ${name}) then
+          throw "Source `${name}` replacing `${from}` is not defined"
+        else
+          registries.${name}
+            or (throw "Source `${name}` replacing `${from}` not found. Please define it in `extraRegistries` with its name.");
+
+      # Git sources are not replaced, since they are provided by `gitSrcs` anyway.
+      replaced = filter
+        (name: sources.${name} ? replace-with && sourceUrl name != null)
+        (attrNames sources);
+    in
+      registries // listToAttrs (map (name: {
+        name = sourceUrl name;
+        value = getRegistry name (replacementOf [] name);
+      }) replaced);
+
+  # Check `package.rust-version` against the version of rustc, like cargo does.
+  # Pre-release rustc satisfies its release version, eg. 1.66.0-nightly satisfies 1.66.
+  # Return `true` or throw.
+  checkRustVersion = { name, version, rustVersion ? null, ... }: rustc: let
+    rustcVersion = head (splitString "-" (getVersion rustc));
+  in
+    # Versions of custom toolchains may be unknown.
+    rustVersion == null || rustcVersion == "" || compareVersions rustcVersion rustVersion >= 0
+    || throw ''
+      Package `${name} ${version}` requires rustc ${rustVersion} or newer, but rustc ${rustcVersion} is used.
+      Please use a newer toolchain via `rustc`, `toolchains` or `buildCrateOverrides`.
+    '';
+
   mkRustPackageOrWorkspace =
-    { defaultRegistries, pkgsBuildHost, buildRustCrate, stdenv }@default:
+    { defaultRegistries, pkgsBuildHost, buildRustCrate, stdenv, rust }@default:
     { src # : Path
     , gitSrcs ? {} # : Attrset Path
     , buildCrateOverrides ? {} # : Attrset (Attrset _)
     , extraRegistries ? {} # : Attrset Registry
     , registries ? defaultRegistries // extraRegistries
 
+    # Like `$CARGO_HOME/config.toml`, which has a lower precedence than the one of the project.
+    , extraCargoConfig ? {} # : <fromTOML>
+
+    , rustflags ? null # : null | [String]
+    , targetRustflags ? {} # : Attrset [String]
+
     , rustc ? pkgsBuildHost.rustc
+    # Build with each of toolchains, as `<toolchain-name>.<profile>.<pkg>`, instead of `rustc`.
+    , toolchains ? null # : null | Attrset Derivation
     , stdenv ? default.stdenv
     }:
     let
       manifest = fromTOML (readFile (src + "/Cargo.toml"));
 
+      # Only the config of `src` is loaded, as cargo does when building in the workspace root.
+      # Configs of members are ignored, since cargo only discovers them when running inside members.
+      cargoConfig = mergeCargoConfigs [ extraCargoConfig (loadCargoConfig src) ];
+
       profiles = profilesFromManifest manifest;
+      resolver = resolverFromManifest manifest;
 
       selected = flatten (map (glob: globMatchDir glob src) manifest.workspace.members);
       excluded = map sanitizeRelativePath (manifest.workspace.exclude or []);
@@ -106,27 +281,69 @@ rec {
         (map (relativePath:
           let
             memberRoot = src + ("/" + relativePath);
-            memberManifest = fromTOML (readFile (memberRoot + "/Cargo.toml")) // lockVersionSet;
+            memberManifest =
+              inheritWorkspaceManifest manifest relativePath (fromTOML (readFile (memberRoot + "/Cargo.toml")))
+              // lockVersionSet;
           in {
             name = toPkgId memberManifest.package;
-            value = mkPkgInfoFromCargoToml memberManifest memberRoot;
+            value = mkPkgInfoFromCargoToml memberManifest memberRoot
+              # Builders need the root manifest to fill inherited `[package]` fields and dependencies.
+              // optionalAttrs (manifest ? workspace) {
+                workspaceManifest = src + "/Cargo.toml";
+              };
           }
           ) (if manifest ? workspace then members else [ "" ]));
 
-    in mkRustPackageSet {
-      gitSrcInfos = mapAttrs (url: src:
-        mkPkgInfoFromCargoToml (fromTOML (readFile (src + "/Cargo.toml")) // lockVersionSet) src
-      ) gitSrcs;
+      # Path crates from `[patch]` of the manifest or the config, which are local but not members.
+      # Other patches need no special care, since `Cargo.lock` records the patched sources.
+      patchSrcInfos =
+        listToAttrs
+        (map (relativePath:
+          let
+            patchRoot = src + ("/" + sanitizeRelativePath relativePath);
+            patchManifest = fromTOML (readFile (patchRoot + "/Cargo.toml")) // lockVersionSet;
+          in {
+            name = toPkgId patchManifest.package;
+            value = mkPkgInfoFromCargoToml patchManifest patchRoot;
+          })
+          (concatMap
+            (patches: filter (path: path != null) (map (dep: dep.path or null) (attrValues patches)))
+            (attrValues (manifest.patch or {}) ++ attrValues (cargoConfig.patch or {}))));
+
+      mkWorkspace = rustc: mkRustPackageSet {
+        gitSrcInfos = mapAttrs (url: src:
+          mkPkgInfoFromCargoToml (fromTOML (readFile (src + "/Cargo.toml")) // lockVersionSet) src
+        ) gitSrcs;
+
+        rustflags = rustflagsFromConfig {
+          config = cargoConfig;
+          inherit rustflags targetRustflags;
+          hostTriple = rust.toRustTarget stdenv.hostPlatform;
+          hostCfgs = platformToCfgs stdenv.hostPlatform;
+          root = src;
+        };
 
-      inherit lock profiles localSrcInfos buildRustCrate buildCrateOverrides registries rustc stdenv;
-    };
+        cargoEnv = envFromConfig cargoConfig src;
+        registries = registriesFromConfig cargoConfig registries;
+
+        inherit lock profiles resolver localSrcInfos patchSrcInfos buildRustCrate buildCrateOverrides rustc stdenv;
+      };
+    in
+      if toolchains == null then
+        mkWorkspace rustc
+      else
+        mapAttrs (_: mkWorkspace) toolchains;
 
   # -> { <profile-name> = { <member-pkg-name> = <drv>; }; }
   mkRustPackageSet =
     { lock # : <fromTOML>
     , localSrcInfos # : Attrset PkgInfo
+    , patchSrcInfos ? {} # : Attrset PkgInfo
     , gitSrcInfos # : Attrset PkgInfo
     , profiles # : Attrset Profile
+    , resolver ? "1" # : "1" | "2" | "3"
+    , rustflags ? [] # : [String]
+    , cargoEnv ? {} # : Attrset String
     , buildCrateOverrides # : Attrset (Attrset _)
     , buildRustCrate # : Attrset -> Derivation
     , registries # : Attrset Registry
@@ -145,6 +362,7 @@ rec {
         # Local crates have no `source`.
         if source == null then
           localSrcInfos.${toPkgId args}
+            or patchSrcInfos.${toPkgId args}
             or (throw "Local crate is outside the workspace: ${toPkgId args}")
           // { isLocalPkg = true; }
         else if m == null then
@@ -172,7 +390,7 @@ rec {
 
       selectDeps = pkgs: deps: features: selectKind: onlyLinks:
         map
-          (dep: { rename = dep.rename or null; drv = pkgs.${dep.resolved}; })
+          (dep: { inherit (dep) name; rename = dep.rename or null; drv = pkgs.${dep.resolved}; })
           (filter
             ({ kind, name, optional, targetEnabled, resolved, ... }@dep:
               targetEnabled && kind == selectKind
@@ -181,75 +399,181 @@ rec {
               && (onlyLinks -> pkgSet.${resolved}.links != null))
             deps);
 
-      buildRustCrate' = info: args:
+      applyOverrides = info: args0:
         let
+          args = args0 // optionalAttrs (info ? workspaceManifest) {
+            inherit (info) workspaceManifest;
+          };
           # TODO: Proc macro crates should behave differently in dependency resolution.
           # But this override is applied just before the `buildRustCrate` call.
           args' = args // (info.__override or lib.id) args;
-          args'' = args' // (buildCrateOverrides.${toPkgId info} or lib.id) args';
         in
-          buildRustCrate args'';
+          args' // (buildCrateOverrides.${toPkgId info} or lib.id) args';
 
-      mkPkg = profile: rootId: makeOverridable (
+      # Build a crate with `args` from `set`, where dependencies are built by the same rustc.
+      # If overrides choose another rustc, arguments are taken from the set of that rustc instead,
+      # as `argsIn otherSet`. So dependencies follow the crate, but dependents don't. Since rustc
+      # rejects crates built by a different compiler, dependencies with another rustc are errors.
+      buildRustCrate' = info: set: args: argsIn:
+        let
+          args' = if args.rustc == set.rustc then args else argsIn (set.setFor args.rustc);
+          deps = args'.dependencies or [] ++ args'.buildDependencies or [] ++ args'.devDependencies or [];
+          mismatched = filter (dep: dep.drv.rustc != args'.rustc) deps;
+          dep = (head mismatched).drv;
+          describeRustc = rustc: if isAttrs rustc then rustc.name or "rustc" else toString rustc;
+        in
+          assert checkRustVersion info args'.rustc;
+          assert mismatched == [] || throw ''
+            Package `${info.name} ${info.version}` is built with ${describeRustc args'.rustc}, but its dependency `${dep.pname} ${dep.version}` is built with ${describeRustc dep.rustc}.
+            Rustc rejects crates built by a different compiler. Please also override `rustc` of its dependents via `buildCrateOverrides`,
+            or use `rustc` or `toolchains` for the whole workspace.
+          '';
+          buildRustCrate args';
+
+      # `memberPkgs` are members built with the same profile, whose docs are merged into the root's.
+      mkPkg = profile: memberPkgs: rootId: makeOverridable (
         { features }:
         let
           rootFeatures = if features != null then features
             else if pkgSet.${rootId}.features ? default then [ "default" ]
             else [];
 
-          resolvedBuildFeatures = resolveFeatures {
-            inherit pkgSet rootId rootFeatures;
-            depFilter = dep: dep.targetEnabled && dep.kind == "normal" || dep.kind == "build";
-          };
-          resolvedNormalFeatures = resolveFeatures {
-            inherit pkgSet rootId rootFeatures;
-            depFilter = dep: dep.targetEnabled && dep.kind == "normal";
-          };
-
-          pkgsBuild = mapAttrs (id: features: let info = pkgSet.${id}; in
-            if features != null then
-              buildRustCrate' info {
-                inherit (info) version src procMacro;
-                inherit features profile rustc;
-                pname = info.name;
-                capLints = if localSrcInfos ? id then null else "allow";
-                buildDependencies = selectDeps pkgsBuild info.dependencies features "build" false;
-                # Build dependency's normal dependency is still build dependency.
-                dependencies = selectDeps pkgsBuild info.dependencies features "normal" false;
-                linksDependencies = selectDeps pkgsBuild info.dependencies features "normal" true;
-              }
-            else
-              null
-          ) resolvedBuildFeatures;
-
-          pkgs = mapAttrs (id: features: let info = pkgSet.${id}; in
-            if features != null then
-              buildRustCrate' info {
-                inherit (info) version src links procMacro;
-                inherit features profile rustc;
-                pname = info.name;
-                capLints = if localSrcInfos ? id then null else "allow";
-                buildDependencies = selectDeps pkgsBuild info.dependencies features "build" false;
-                dependencies = selectDeps pkgs info.dependencies features "normal" false;
-                linksDependencies = selectDeps pkgs info.dependencies features "normal" true;
-              }
-            else
-              null
-          ) resolvedNormalFeatures;
+          # Dev-dependencies of the root package are only enabled for its tests, examples and benches.
+          mkPkgs = profile: withDev: let
+            resolved = resolveFeaturesWithResolver {
+              inherit pkgSet rootId rootFeatures resolver withDev;
+            };
+
+            defaultSet = mkSet rustc;
+            # Sets of other rustc chosen by overrides, shared by all crates choosing the same one.
+            otherSets = listToAttrs (map (rustc': { name = toString rustc'; value = mkSet rustc'; })
+              (filter (rustc': rustc' != rustc) defaultSet.chosenRustcs));
+            setFor = rustc':
+              if rustc' == rustc then defaultSet
+              else otherSets.${toString rustc'} or (mkSet rustc');
+
+            mkSet = rustc: let
+              self = {
+                inherit rustc setFor buildArgs targetArgs pkgsBuild pkgs pkgs';
+                chosenRustcs = map (args: args.rustc)
+                  (filter (args: args != null) (attrValues buildArgs ++ attrValues targetArgs));
+              };
+
+              # Arguments of crates with dependencies from this set, with overrides applied.
+              buildArgs = mapAttrs (id: features: let info = pkgSet.${id}; in
+                if features != null then
+                  applyOverrides info {
+                    inherit (info) version src procMacro;
+                    inherit features rustc rustflags cargoEnv;
+                    profile = profileForPkg profile info true;
+                    pname = info.name;
+                    capLints = if localSrcInfos ? id then null else "allow";
+                    buildDependencies = selectDeps pkgsBuild info.dependencies features "build" false;
+                    # Build dependency's normal dependency is still build dependency.
+                    dependencies = selectDeps pkgsBuild info.dependencies features "normal" false;
+                    linksDependencies = selectDeps pkgsBuild info.dependencies features "normal" true;
+                  }
+                else
+                  null
+              ) resolved.host;
+
+              targetArgs = mapAttrs (id: features: let info = pkgSet.${id}; in
+                if features != null then
+                  applyOverrides info ({
+                    inherit (info) version src links procMacro;
+                    inherit features rustc rustflags cargoEnv;
+                    profile = profileForPkg profile info false;
+                    pname = info.name;
+                    capLints = if localSrcInfos ? id then null else "allow";
+                    buildDependencies = selectDeps pkgsBuild info.dependencies features "build" false;
+                    dependencies = selectDeps pkgs' info.dependencies features "normal" false;
+                    linksDependencies = selectDeps pkgs' info.dependencies features "normal" true;
+                  } // optionalAttrs (id == rootId) {
+                    devDependencies = selectDeps pkgs' info.dependencies features "dev" false;
+                  })
+                else
+                  null
+              ) resolved.target;
+
+              pkgsBuild = mapAttrs (id: args:
+                if args != null then
+                  buildRustCrate' pkgSet.${id} self args (set: set.buildArgs.${id})
+                else
+                  null
+              ) buildArgs;
+
+              pkgs = mapAttrs (id: args:
+                if args != null then
+                  buildRustCrate' pkgSet.${id} self args (set: set.targetArgs.${id})
+                else
+                  null
+              ) targetArgs;
+
+              # Proc-macros run on the host, so they are built with features resolved for the host.
+              pkgs' = mapAttrs (id: pkg: if pkgSet.${id}.procMacro then pkgsBuild.${id} else pkg) pkgs;
+            in
+              self;
+          in
+            defaultSet.pkgs;
+
+          pkg = (mkPkgs profile false).${rootId};
+          devPkg = (mkPkgs profile true).${rootId};
+          # Tests are always built with the `test` profile, as `cargo test` does.
+          testPkg = (mkPkgs profiles.test true).${rootId};
+          # Benches are always built with the `bench` profile, as `cargo bench` does.
+          benchPkg = (mkPkgs profiles.bench true).${rootId};
         in
-          pkgs.${rootId}
+          pkg // {
+            inherit (devPkg) examples;
+            inherit (testPkg) tests check doctest;
+            inherit (benchPkg) benches;
+
+            # Docs of the member and its member dependencies merged into one tree, as `cargo doc -p` does.
+            # Members are documented one by one on top of previous ones, so rustdoc links to them.
+            doc = foldl'
+              (base: id: (if id == rootId then pkg else memberPkgs.${id}).mkDoc base)
+              null
+              (filter (id: elem id (localDepsClosure rootId)) sortedMemberIds);
+
+            # Docs of all members merged into one tree, as `cargo doc --workspace` does.
+            # It is the same derivation for every member of a profile.
+            workspaceDoc = foldl' (base: id: memberPkgs.${id}.mkDoc base) null sortedMemberIds;
+          }
       ) {
         features = null;
       };
 
-    in
-      mapAttrs (_: profile:
-        mapAttrs' (pkgId: pkgInfo: {
-          name = pkgInfo.name;
-          value = mkPkg profile pkgId;
-        }) localSrcInfos
+      pkgsByProfile = mapAttrs (name: profile:
+        mapAttrs (pkgId: _: mkPkg profile pkgsByProfile.${name} pkgId) localSrcInfos
       ) profiles;
 
+      # Member dependencies of a member. Dev-dependencies may form cycles, and don't matter for docs.
+      localDeps = id:
+        map (dep: dep.resolved)
+          (filter (dep: dep.kind != "dev" && dep.resolved != null && localSrcInfos ? ${dep.resolved})
+            pkgSet.${id}.dependencies);
+
+      # The member itself and its member dependencies, recursively.
+      localDepsClosure = id:
+        map ({ key }: key) (genericClosure {
+          startSet = [ { key = id; } ];
+          operator = { key }: map (key: { inherit key; }) (localDeps key);
+        });
+
+      # Members in dependency order.
+      sortedMemberIds = let
+        sorted = toposort (a: b: elem a (localDeps b)) (attrNames localSrcInfos);
+      in
+        sorted.result or (throw "Cyclic dependencies between workspace members: ${toJSON sorted.cycle}");
+
+    in
+      mapAttrs (_: pkgs:
+        mapAttrs' (pkgId: pkg: {
+          name = localSrcInfos.${pkgId}.name;
+          value = pkg;
+        }) pkgs
+      ) pkgsByProfile;
+
   sanitizeRelativePath = path:
     if hasPrefix "/" path then
       throw "Absolute path is not allowed: ${path}"
@@ -299,6 +623,45 @@ rec {
     in
       assertEq serde.buildInputs [ "some-inputs" ];
 
+    # Dependencies follow the rustc of the crate.
+    rustc-overrided = let
+      ret = build ../tests/features {
+        buildCrateOverrides.simple-features = old: {
+          rustc = "nightly";
+        };
+      };
+      semver = (head ret.dependencies).drv;
+      serde = (head semver.dependencies).drv;
+    in
+      assertEq [ ret.rustc semver.rustc serde.rustc ] [ "nightly" "nightly" "nightly" ];
+
+    # Dependents must use the same rustc.
+    rustc-overrided-dependency = let
+      ret = build ../tests/features {
+        buildCrateOverrides."semver 1.0.12 (registry+https://github.com/rust-lang/crates.io-index)" = old: {
+          rustc = "nightly";
+        };
+      };
+    in
+      assertEq (tryEval ret.rustc).success false;
+
+    toolchains = let
+      ws = mkPackage {
+        src = ../tests/features;
+        toolchains = { stable = "stable"; msrv = "msrv"; };
+      };
+      rustcOf = toolchain: (head (attrValues ws.${toolchain}.release)).rustc;
+    in
+      assertEq [ (rustcOf "stable") (rustcOf "msrv") ] [ "stable" "msrv" ];
+
+    cargo-config = let
+      ret = build ../tests/cargo-config {};
+      semver = (head ret.dependencies).drv;
+    in
+      assertEq
+        [ ret.cargoEnv.GREETING semver.version semver.src ]
+        [ "Hello" "1.99.0" ../tests/cargo-config/semver ];
+
     dependency-kinds = let
       mkSrc = from: { __toString = _: ../tests/fake-semver; inherit from; };
       gitSrcs = {
@@ -331,6 +694,43 @@ rec {
         registry_index = "crate-semver-1.0.12.tar.gz";
       };
 
+    dev-dependencies = let
+      ret = (mkPackage { src = ../tests/test-targets; }).dev.foo;
+    in
+      assertEq ret.dependencies [ ];
+
+    profile-overrides = let
+      ret = build ../tests/profile-overrides {};
+      semver = (head ret.dependencies).drv;
+      semverBuild = (head ret.buildDependencies).drv;
+      select = p: {
+        inherit (p) opt-level codegen-units debug-assertions;
+        build-override = { inherit (p.build-override) opt-level codegen-units; };
+      };
+    in
+      assertEq (map (drv: select drv.profile) [ ret semver semverBuild ]) [
+        # Members are not affected by `"*"`.
+        {
+          opt-level = 0;
+          codegen-units = 256;
+          debug-assertions = false;
+          build-override = { opt-level = 0; codegen-units = 4; };
+        }
+        {
+          opt-level = 3;
+          codegen-units = 256;
+          debug-assertions = true;
+          build-override = { opt-level = 3; codegen-units = 4; };
+        }
+        # Package overrides take precedence over `build-override`.
+        {
+          opt-level = 3;
+          codegen-units = 4;
+          debug-assertions = true;
+          build-override = { opt-level = 3; codegen-units = 4; };
+        }
+      ];
+
     libz-propagated = let
       ret = build ../tests/libz-dynamic {};
       libz = (head ret.dependencies).drv;
@@ -345,6 +745,114 @@ rec {
       assertEq [ libz.links libz'.links ] [ "z" "z" ];
   };
 
+  resolver-from-manifest-tests = { assertEq, ... }: let
+    test = manifest: expect: assertEq (resolverFromManifest manifest) expect;
+  in
+  {
+    edition-default = test { package.name = "foo"; } "1";
+    edition-2018 = test { package.edition = "2018"; } "1";
+    edition-2021 = test { package.edition = "2021"; } "2";
+    edition-2024 = test { package.edition = "2024"; } "3";
+    edition-inherited = test {
+      package.edition.workspace = true;
+      workspace.package.edition = "2021";
+    } "2";
+    package = test { package = { edition = "2018"; resolver = "2"; }; } "2";
+    workspace = test { package.edition = "2021"; workspace.resolver = "1"; } "1";
+    virtual-workspace = test { workspace.members = [ "foo" ]; } "1";
+  };
+
+  rustflags-from-config-tests = { assertEq, ... }: let
+    test = args: expect: assertEq (rustflagsFromConfig ({
+      config = {};
+      rustflags = null;
+      targetRustflags = {};
+      hostTriple = "x86_64-unknown-linux-gnu";
+      hostCfgs = [ { key = "unix"; } ];
+      root = "/src";
+    } // args)) expect;
+
+    config = {
+      build.rustflags = "-Ctarget-cpu=native  --cfg foo";
+      target."cfg(unix)".rustflags = [ "--cfg" "unix" ];
+      target."cfg(windows)".rustflags = [ "--cfg" "windows" ];
+      target.x86_64-unknown-linux-gnu.rustflags = "--cfg triple";
+    };
+  in
+  {
+    none = test {} [];
+    build = test { config.build = config.build; } [ "-Ctarget-cpu=native" "--cfg" "foo" ];
+    target = test { inherit config; } [ "--cfg" "unix" "--cfg" "triple" ];
+    target-args = test {
+      inherit config;
+      targetRustflags."cfg(unix)" = [ "--cfg" "tokio_unstable" ];
+    } [ "--cfg" "tokio_unstable" "--cfg" "triple" ];
+    args = test {
+      inherit config;
+      rustflags = [ "--cfg" "args" ];
+    } [ "--cfg" "args" ];
+    linker = test {
+      config.target.x86_64-unknown-linux-gnu.linker = "tools/cc";
+      config.target.aarch64-unknown-linux-gnu.linker = "aarch64-cc";
+    } [ "-Clinker=/src/tools/cc" ];
+  };
+
+  rust-version-tests = { assertEq, ... }: let
+    check = rustVersion: rustc:
+      (tryEval (checkRustVersion { name = "foo"; version = "0.1.0"; inherit rustVersion; } rustc)).success;
+  in
+  {
+    none = assertEq (check null "rustc-1.0.0") true;
+    newer = assertEq (check "1.64" "rustc-1.65.0") true;
+    same = assertEq (check "1.64.0" "rustc-1.64.0") true;
+    nightly = assertEq (check "1.66" "rust-default-1.66.0-nightly-2022-10-01") true;
+    older = assertEq (check "1.64" "rustc-1.63.0") false;
+    unknown = assertEq (check "1.64" "my-rustc") true;
+  };
+
+  cargo-config-tests = { assertEq, ... }: let
+    registries = {
+      "https://github.com/rust-lang/crates.io-index" = "crates-io";
+      "https://mirror.example.com/index" = "mirror";
+      vendored = "vendored";
+    };
+    replaced = config: registriesFromConfig config registries;
+  in
+  {
+    merge = assertEq
+      (mergeCargoConfigs [
+        { env.A = "1"; env.B = "2"; build.rustflags = [ "-a" ]; }
+        { env.B = { value = "3"; force = true; }; build.rustflags = [ "-b" ]; }
+      ])
+      { env.A = "1"; env.B = { value = "3"; force = true; }; build.rustflags = [ "-a" "-b" ]; };
+
+    env = assertEq
+      (envFromConfig {
+        env.A = "a";
+        env.B = { value = "b"; force = true; };
+        env.C = { value = "c"; relative = true; };
+      } "/src")
+      { A = "a"; B = "b"; C = "/src/c"; };
+
+    no-replace = assertEq (replaced {}) registries;
+    replace-registry = assertEq
+      (replaced {
+        source.crates-io.replace-with = "mirror";
+        source.mirror.registry = "https://mirror.example.com/index";
+      })."https://github.com/rust-lang/crates.io-index"
+      "mirror";
+    replace-chain = assertEq
+      (replaced {
+        source.crates-io.replace-with = "mirror";
+        source.mirror = { registry = "https://mirror.example.com/index"; replace-with = "vendored"; };
+        source.vendored.directory = "vendor";
+      })
+      (registries // {
+        "https://github.com/rust-lang/crates.io-index" = "vendored";
+        "https://mirror.example.com/index" = "vendored";
+      });
+  };
+
   sanitize-relative-path-tests = { assertEq, ... }: let
     assertOk = raw: expect: assertEq (tryEval (sanitizeRelativePath raw)) { success = true; value = expect; };
     assertInvalid = raw: assertEq (tryEval (sanitizeRelativePath raw)) { success = false; value = false; };
@@ -0,0 +1,394 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// Predict which crates.io crates of a workspace are substituted from the binary cache.
+///
+/// Dependency graphs of both the workspace and the cache are resolved by `cargo tree`, per
+/// workspace member as nocargo does. A crate is cached only if its version, features and all its
+/// dependencies are the same, since any difference changes the derivation.
+/// Profiles and rustflags are not compared, and assumed to be the default ones.
+#[derive(clap::Args)]
+pub struct Args {
+    /// The path to `Cargo.toml` of the workspace.
+    #[clap(long, default_value = "Cargo.toml")]
+    manifest_path: PathBuf,
+
+    /// The `cache` directory of a nocargo checkout, with `Cargo.lock` generated.
+    #[clap(long)]
+    cache: PathBuf,
+
+    /// The version of rustc building the workspace. Default to the one of `rustc` in `PATH`.
+    #[clap(long)]
+    rustc: Option<String>,
+
+    /// The version of rustc building the cache. Rustc is not compared if omitted.
+    #[clap(long)]
+    cache_rustc: Option<String>,
+}
+
+impl super::App for Args {
+    fn run(self) -> Result<()> {
+        let rustc_mismatch = match &self.cache_rustc {
+            Some(cache_rustc) => {
+                let rustc = match self.rustc {
+                    Some(rustc) => rustc,
+                    None => rustc_version()?,
+                };
+                (rustc != *cache_rustc)
+                    .then(|| format!("different rustc {}, cached with {}", rustc, cache_rustc))
+            }
+            None => None,
+        };
+
+        let cache_manifest = self.cache.join("Cargo.toml");
+        let mut cache = Cache::default();
+        for member in workspace_members(&cache_manifest)? {
+            cache.add(&resolve_tree(&cache_manifest, &member)?);
+        }
+
+        let mut total = BTreeSet::new();
+        let mut misses = BTreeMap::new();
+        for member in workspace_members(&self.manifest_path)? {
+            let graph = resolve_tree(&self.manifest_path, &member)?;
+            let mut checker = Checker::new(&cache, &graph, rustc_mismatch.as_deref());
+            for node in graph.keys().filter(|node| node.crates_io) {
+                total.insert(node.clone());
+                if let Some(reason) = checker.check(node) {
+                    misses.insert(node.clone(), reason);
+                }
+            }
+        }
+
+        for (node, reason) in &misses {
+            println!("{} {}: {}", node.name, node.version, reason);
+        }
+        eprintln!(
+            "{} of {} crates.io crates are cached",
+            total.len() - misses.len(),
+            total.len(),
+        );
+        Ok(())
+    }
+}
+
+fn rustc_version() -> Result<String> {
+    let output = Command::new("rustc")
+        .arg("--version")
+        .output()
+        .context("Failed to run rustc")?;
+    ensure!(output.status.success(), "Failed to run rustc");
+    let output = String::from_utf8(output.stdout)?;
+    // `rustc 1.64.0 (a55dd71d5 2022-09-19)`
+    output
+        .split_whitespace()
+        .nth(1)
+        .map(|s| s.to_owned())
+        .context("Invalid output of `rustc --version`")
+}
+
+fn workspace_members(manifest_path: &Path) -> Result<Vec<String>> {
+    let output = Command::new("cargo")
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version=1",
+            "--manifest-path",
+        ])
+        .arg(manifest_path)
+        .output()
+        .context("Failed to run cargo")?;
+    ensure!(
+        output.status.success(),
+        "Failed to get metadata of {}:\n{}",
+        manifest_path.display(),
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let metadata = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    metadata["packages"]
+        .as_array()
+        .context("Invalid metadata")?
+        .iter()
+        .map(|pkg| {
+            pkg["name"]
+                .as_str()
+                .map(|s| s.to_owned())
+                .context("Invalid metadata")
+        })
+        .collect()
+}
+
+fn resolve_tree(manifest_path: &Path, member: &str) -> Result<Graph> {
+    let output = Command::new("cargo")
+        .args(["tree", "--edges=normal,build", "--prefix=depth"])
+        .args(["--format={p}|{f}", "--package", member, "--manifest-path"])
+        .arg(manifest_path)
+        .output()
+        .context("Failed to run cargo")?;
+    ensure!(
+        output.status.success(),
+        "Failed to resolve {} of {}:\n{}",
+        member,
+        manifest_path.display(),
+        String::from_utf8_lossy(&output.stderr),
+    );
+    parse_tree(&String::from_utf8(output.stdout)?)
+        .with_context(|| format!("Invalid output of cargo tree for {}", member))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Node {
+    name: String,
+    version: String,
+    features: Vec<String>,
+    crates_io: bool,
+}
+
+/// Dependencies of each node.
+type Graph = BTreeMap<Node, BTreeSet<Node>>;
+
+/// Parse the output of `cargo tree --prefix=depth --format='{p}|{f}'`.
+fn parse_tree(output: &str) -> Result<Graph> {
+    let mut graph = Graph::new();
+    let mut stack = Vec::<Node>::new();
+    for line in output.lines().filter(|line| !line.is_empty()) {
+        let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+        let depth = line[..digits].parse::<usize>().context("Missing depth")?;
+        let (pkg, features) = line[digits..]
+            .split_once('|')
+            .with_context(|| format!("Invalid line: {}", line))?;
+        let features = features.trim_end_matches(" (*)");
+
+        // `name v1.0.0`, with optional `(proc-macro)` and the source if it's not crates.io.
+        let mut words = pkg.split(' ');
+        let (name, version) = match (words.next(), words.next()) {
+            (Some(name), Some(version)) if version.starts_with('v') => (name, &version[1..]),
+            _ => bail!("Invalid line: {}", line),
+        };
+        let node = Node {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            features: features
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+                .collect(),
+            crates_io: words.all(|word| word == "(proc-macro)"),
+        };
+
+        ensure!(depth <= stack.len(), "Invalid depth: {}", line);
+        stack.truncate(depth);
+        if let Some(parent) = stack.last() {
+            graph.get_mut(parent).unwrap().insert(node.clone());
+        }
+        graph.entry(node.clone()).or_default();
+        stack.push(node);
+    }
+    Ok(graph)
+}
+
+/// Hash a node with all its dependencies recursively, which identifies its derivation.
+fn node_hash(graph: &Graph, node: &Node, memo: &mut BTreeMap<Node, u64>) -> u64 {
+    if let Some(&hash) = memo.get(node) {
+        return hash;
+    }
+    let mut hasher = DefaultHasher::new();
+    node.hash(&mut hasher);
+    for dep in &graph[node] {
+        node_hash(graph, dep, memo).hash(&mut hasher);
+    }
+    let hash = hasher.finish();
+    memo.insert(node.clone(), hash);
+    hash
+}
+
+#[derive(Default)]
+struct Cache {
+    hashes: HashSet<u64>,
+    /// Feature sets of each crate version.
+    features: BTreeMap<(String, String), BTreeSet<Vec<String>>>,
+}
+
+impl Cache {
+    fn add(&mut self, graph: &Graph) {
+        let mut memo = BTreeMap::new();
+        for node in graph.keys() {
+            self.hashes.insert(node_hash(graph, node, &mut memo));
+            self.features
+                .entry((node.name.clone(), node.version.clone()))
+                .or_default()
+                .insert(node.features.clone());
+        }
+    }
+}
+
+struct Checker<'a> {
+    cache: &'a Cache,
+    graph: &'a Graph,
+    rustc_mismatch: Option<&'a str>,
+    hashes: BTreeMap<Node, u64>,
+    results: BTreeMap<Node, Option<String>>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(cache: &'a Cache, graph: &'a Graph, rustc_mismatch: Option<&'a str>) -> Self {
+        Self {
+            cache,
+            graph,
+            rustc_mismatch,
+            hashes: BTreeMap::new(),
+            results: BTreeMap::new(),
+        }
+    }
+
+    /// Return the reason if the node misses the cache.
+    fn check(&mut self, node: &Node) -> Option<String> {
+        if let Some(result) = self.results.get(node) {
+            return result.clone();
+        }
+        let result = self.check_uncached(node);
+        self.results.insert(node.clone(), result.clone());
+        result
+    }
+
+    fn check_uncached(&mut self, node: &Node) -> Option<String> {
+        if !node.crates_io {
+            return Some("not from crates.io".into());
+        }
+        if let Some(reason) = self.rustc_mismatch {
+            return Some(reason.into());
+        }
+        let cached_features = match self
+            .cache
+            .features
+            .get(&(node.name.clone(), node.version.clone()))
+        {
+            Some(features) => features,
+            None => return Some("version not cached".into()),
+        };
+        if !cached_features.contains(&node.features) {
+            let cached = cached_features
+                .iter()
+                .map(|features| format!("[{}]", features.join(",")))
+                .collect::<Vec<_>>();
+            return Some(format!(
+                "feature set [{}] differs, cached with {}",
+                node.features.join(","),
+                cached.join(" "),
+            ));
+        }
+        for dep in &self.graph[node] {
+            if self.check(dep).is_some() {
+                return Some(format!("dependency {} {} misses", dep.name, dep.version));
+            }
+        }
+        if !self
+            .cache
+            .hashes
+            .contains(&node_hash(self.graph, node, &mut self.hashes))
+        {
+            return Some("dependencies resolved differently".into());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, version: &str, features: &[&str]) -> Node {
+        Node {
+            name: name.into(),
+            version: version.into(),
+            features: features.iter().map(|s| s.to_string()).collect(),
+            crates_io: true,
+        }
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let graph = parse_tree(
+            "\
+0foo v0.1.0 (/path/to/foo)|
+1serde v1.0.145|default,derive,serde_derive,std
+2serde_derive v1.0.145 (proc-macro)|default
+1serde_derive v1.0.145 (proc-macro)|default (*)
+",
+        )
+        .unwrap();
+        let serde = node(
+            "serde",
+            "1.0.145",
+            &["default", "derive", "serde_derive", "std"],
+        );
+        let serde_derive = node("serde_derive", "1.0.145", &["default"]);
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph[&serde], [serde_derive.clone()].into_iter().collect());
+        assert!(graph[&serde_derive].is_empty());
+        let foo = graph.keys().find(|node| node.name == "foo").unwrap();
+        assert!(!foo.crates_io);
+        assert_eq!(graph[foo], [serde, serde_derive].into_iter().collect());
+
+        assert!(parse_tree("0foo v0.1.0|\n2bar v0.1.0|\n").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut cache = Cache::default();
+        cache.add(
+            &parse_tree(
+                "\
+0cache v0.0.0 (/cache)|
+1log v0.4.17|std
+2cfg-if v1.0.0|
+1itoa v1.0.3|
+",
+            )
+            .unwrap(),
+        );
+
+        let graph = parse_tree(
+            "\
+0foo v0.1.0 (/foo)|
+1log v0.4.17|std
+2cfg-if v0.1.10|
+1itoa v1.0.3|
+1ryu v1.0.11|default
+1libc v0.2.132|
+",
+        )
+        .unwrap();
+        let mut checker = Checker::new(&cache, &graph, None);
+        let mut check = |name, version, features| checker.check(&node(name, version, features));
+        assert_eq!(check("itoa", "1.0.3", &[]), None);
+        assert_eq!(
+            check("log", "0.4.17", &["std"]).as_deref(),
+            Some("dependency cfg-if 0.1.10 misses")
+        );
+        assert_eq!(
+            check("cfg-if", "0.1.10", &[]).as_deref(),
+            Some("version not cached")
+        );
+        assert_eq!(
+            check("ryu", "1.0.11", &["default"]).as_deref(),
+            Some("version not cached")
+        );
+
+        let graph = parse_tree("0foo v0.1.0 (/foo)|\n1log v0.4.17|\n2cfg-if v1.0.0|\n").unwrap();
+        let mut checker = Checker::new(&cache, &graph, None);
+        assert_eq!(
+            checker.check(&node("log", "0.4.17", &[])).as_deref(),
+            Some("feature set [] differs, cached with [std]")
+        );
+        let mut checker = Checker::new(&cache, &graph, Some("different rustc"));
+        assert_eq!(
+            checker.check(&node("cfg-if", "1.0.0", &[])).as_deref(),
+            Some("different rustc")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use serde_json::{json, Value};
+
+use crate::vendor::{index_path, LockedPackage, CRATES_IO_INDEX};
+
+/// Write a subset of a registry index, containing only crates referenced by `Cargo.lock`.
+///
+/// The output can be used by `mkIndex` in place of the full index.
+#[derive(clap::Args)]
+pub struct Args {
+    /// The path to `Cargo.lock`.
+    #[clap(long, default_value = "Cargo.lock")]
+    lock: PathBuf,
+
+    /// The checkout of the registry index.
+    #[clap(long)]
+    index: PathBuf,
+
+    /// The output directory.
+    #[clap(long)]
+    out: PathBuf,
+
+    /// Include all versions of referenced crates, rather than only locked ones.
+    #[clap(long)]
+    all_versions: bool,
+
+    /// The registry of the index.
+    #[clap(long, default_value = CRATES_IO_INDEX)]
+    registry: String,
+}
+
+impl super::App for Args {
+    fn run(self) -> Result<()> {
+        let source = format!("registry+{}", self.registry);
+        let mut locked = BTreeMap::<_, BTreeSet<_>>::new();
+        for pkg in LockedPackage::load_all(&self.lock)? {
+            if pkg.source.as_deref() == Some(&*source) {
+                locked.entry(pkg.name).or_default().insert(pkg.version);
+            }
+        }
+
+        let mut files = Vec::new();
+        let mut missing = Vec::new();
+        for (name, versions) in &locked {
+            let path = index_path(name);
+            let src_path = self.index.join(&path);
+            if !src_path.exists() {
+                missing.extend(versions.iter().map(|ver| format!("{} {}", name, ver)));
+                continue;
+            }
+            let content = fs::read_to_string(&src_path)
+                .with_context(|| format!("Failed to read {}", src_path.display()))?;
+            let (lines, found) = filter_lines(&content, versions, self.all_versions)
+                .with_context(|| format!("Invalid index file {}", src_path.display()))?;
+            missing.extend(
+                versions
+                    .difference(&found)
+                    .map(|ver| format!("{} {}", name, ver)),
+            );
+            files.push((path, lines.join("\n") + "\n"));
+        }
+        ensure!(
+            missing.is_empty(),
+            "{} crates are not found in the index:\n{}",
+            missing.len(),
+            missing.join("\n"),
+        );
+
+        fs::create_dir_all(&self.out)?;
+        let config_path = self.index.join("config.json");
+        let dl = (|| -> Result<String> {
+            let config = serde_json::from_str::<Value>(&fs::read_to_string(&config_path)?)?;
+            let dl = config.get("dl").and_then(|v| v.as_str());
+            Ok(dl.context("Missing `dl`")?.to_owned())
+        })()
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        // `source` is not known by cargo. It tells `noc init` which registry this replaces.
+        let config = json!({ "dl": dl, "source": self.registry });
+        fs::write(self.out.join("config.json"), format!("{:#}\n", config))?;
+        for (path, content) in files {
+            let path = self.out.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        eprintln!(
+            "Wrote index of {} crates into {}",
+            locked.len(),
+            self.out.display()
+        );
+        Ok(())
+    }
+}
+
+/// Filter lines of an index file by versions, keeping the original order.
+/// Return the kept lines and the found versions.
+fn filter_lines<'a>(
+    content: &'a str,
+    versions: &BTreeSet<String>,
+    all_versions: bool,
+) -> Result<(Vec<&'a str>, BTreeSet<String>)> {
+    let mut lines = Vec::new();
+    let mut found = BTreeSet::new();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let entry = serde_json::from_str::<Value>(line)?;
+        let vers = entry
+            .get("vers")
+            .and_then(|v| v.as_str())
+            .context("Missing `vers`")?;
+        if versions.contains(vers) {
+            found.insert(vers.to_owned());
+        } else if !all_versions {
+            continue;
+        }
+        lines.push(line);
+    }
+    Ok((lines, found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_lines() {
+        let content = r#"{"name":"foo","vers":"0.1.0"}
+{"name":"foo","vers":"0.2.0"}
+{"name":"foo","vers":"0.3.0"}
+"#;
+        let versions = ["0.3.0", "0.1.0", "0.4.0"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<BTreeSet<_>>();
+
+        let (lines, found) = filter_lines(content, &versions, false).unwrap();
+        assert_eq!(
+            lines,
+            [
+                r#"{"name":"foo","vers":"0.1.0"}"#,
+                r#"{"name":"foo","vers":"0.3.0"}"#
+            ]
+        );
+        assert_eq!(found.into_iter().collect::<Vec<_>>(), ["0.1.0", "0.3.0"]);
+
+        let (lines, _) = filter_lines(content, &versions, true).unwrap();
+        assert_eq!(lines.len(), 3);
+    }
+}
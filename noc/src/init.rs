@@ -1,15 +1,17 @@
 use std::collections::{BTreeMap, HashSet};
-use std::fs::{read_dir, read_to_string, File};
+use std::fs::{self, read_dir, read_to_string, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, ensure, Context, Result};
 use askama::Template;
-use cargo_toml::{Dependency, Manifest, Product};
+use cargo_toml::{Dependency, Manifest, PatchSet, Product};
 use glob::glob;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::vendor::CRATES_IO_INDEX;
+
 /// Create or print template `flake.nix` for your rust crate.
 #[derive(clap::Args)]
 pub struct Args {
@@ -27,6 +29,21 @@ pub struct Args {
     /// Default to be the current directory.
     #[clap(long)]
     root: Option<PathBuf>,
+
+    /// Local registries generated by `noc vendor` or `noc index-subset` inside the project,
+    /// which replace the registries they come from.
+    #[clap(long)]
+    vendored_registry: Vec<PathBuf>,
+
+    /// The flake providing the toolchain pinned by `rust-toolchain.toml`, if any.
+    #[clap(long, value_enum, default_value = "rust-overlay")]
+    toolchain_input: ToolchainInput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ToolchainInput {
+    RustOverlay,
+    Fenix,
 }
 
 impl super::App for Args {
@@ -46,8 +63,12 @@ impl super::App for Args {
             "flake.nix already exists. Use `--force` to overwrite or `--print` to print to stdout only",
         );
 
-        let manifest =
-            Manifest::from_path(root.join("Cargo.toml")).context("Failed to load Cargo.toml")?;
+        let root_manifest_path = root.join("Cargo.toml");
+        let workspace = read_toml(&root_manifest_path)
+            .map(|root_manifest| InheritableWorkspace::from_root_manifest(&root, &root_manifest))
+            .context("Failed to load Cargo.toml")?;
+        let manifest = load_manifest(&root_manifest_path, workspace.as_ref())
+            .context("Failed to load Cargo.toml")?;
 
         // Check ancestor manifest files for (maybe) workspace definition.
         if manifest.workspace.is_none() && self.root.is_none() {
@@ -97,7 +118,14 @@ impl super::App for Args {
             _ => eprintln!("warning: Unsupported version of Cargo.lock, building may fail"),
         }
 
-        let out = generate_flake(&root, &manifest, lock_version)?;
+        let out = generate_flake(
+            &root,
+            &manifest,
+            workspace.as_ref(),
+            lock_version,
+            &self.vendored_registry,
+            self.toolchain_input,
+        )?;
 
         if self.print {
             println!("{}", out);
@@ -118,8 +146,23 @@ impl super::App for Args {
     }
 }
 
-fn generate_flake(root: &Path, manifest: &Manifest, lock_version: i64) -> Result<String> {
-    ensure!(manifest.patch.is_empty(), "[patch] is not supported yet");
+fn generate_flake(
+    root: &Path,
+    manifest: &Manifest,
+    workspace: Option<&InheritableWorkspace>,
+    lock_version: i64,
+    vendored_registries: &[PathBuf],
+    toolchain_input: ToolchainInput,
+) -> Result<String> {
+    let config = CargoConfig::load(root).context("Failed to load .cargo/config.toml")?;
+    let toolchain = load_toolchain_file(root)?.map(|(path, channel)| {
+        eprintln!("note: Picked up toolchain `{}` from {}", channel, path);
+        Toolchain {
+            path,
+            channel,
+            fenix: toolchain_input == ToolchainInput::Fenix,
+        }
+    });
 
     let is_workspace = manifest.workspace.is_some();
     let mut templ = FlakeTemplate {
@@ -127,7 +170,33 @@ fn generate_flake(root: &Path, manifest: &Manifest, lock_version: i64) -> Result
         main_pkg: None,
         registries: Default::default(),
         git_srcs: Default::default(),
+        vendor_dirs: Default::default(),
+        vendored_registries: Default::default(),
+        config_path: config.path.clone(),
+        config_notes: Vec::new(),
+        toolchain,
     };
+    templ.pick_up_config(root, &config)?;
+    for note in &templ.config_notes {
+        eprintln!("note: Picked up from {}: {}", templ.config_path, note);
+    }
+
+    // Patched path crates are local crates, but not necessarily members.
+    for (registry, deps) in manifest.patch.iter().chain(&config.patch) {
+        for (dep_name, dep) in deps {
+            templ
+                .check_dependency(dep, lock_version, |local_path| {
+                    let patch_root = root.join(local_path).canonicalize()?;
+                    ensure!(
+                        patch_root.starts_with(root) && patch_root.join("Cargo.toml").exists(),
+                        "Patched crate is not a package inside the project: {}",
+                        local_path.display(),
+                    );
+                    Ok(())
+                })
+                .with_context(|| format!("In patch {:?} of {:?}", dep_name, registry))?;
+        }
+    }
 
     if let Some(pkg) = &manifest.package {
         templ.main_pkg = Some((
@@ -153,11 +222,28 @@ fn generate_flake(root: &Path, manifest: &Manifest, lock_version: i64) -> Result
                 "Duplicated workspace members"
             );
 
+            // Like `cargo build` in the root, configs of members are not picked up.
+            let abs_root = root.canonicalize()?;
+            for member_root in &member_roots {
+                if member_root.canonicalize()? == abs_root {
+                    continue;
+                }
+                for path in [".cargo/config", ".cargo/config.toml"] {
+                    let path = member_root.join(path);
+                    if path.is_file() {
+                        eprintln!(
+                            "warning: Ignored {}, only the config in the workspace root is picked up",
+                            path.display(),
+                        );
+                    }
+                }
+            }
+
             let member_manifests = member_roots
                 .iter()
                 .map(|root| {
                     let manifest_path = root.join("Cargo.toml");
-                    let manifest = Manifest::from_path(&manifest_path).with_context(|| {
+                    let manifest = load_manifest(&manifest_path, workspace).with_context(|| {
                         format!(
                             "Failed to load member Cargo.toml at {}",
                             manifest_path.display()
@@ -212,6 +298,12 @@ fn generate_flake(root: &Path, manifest: &Manifest, lock_version: i64) -> Result
         }
     }
 
+    for path in vendored_registries {
+        templ
+            .add_vendored_registry(root, path)
+            .with_context(|| format!("Invalid vendored registry at {}", path.display()))?;
+    }
+
     // The trailing newline is suppressed by default. Add it back.
     Ok(templ.render().unwrap() + "\n")
 }
@@ -225,6 +317,43 @@ struct FlakeTemplate {
     registries: BTreeMap<String, String>,
     // source_id ->  flake_ref
     git_srcs: BTreeMap<String, String>,
+    // source_name -> relative path
+    vendor_dirs: BTreeMap<String, String>,
+    // source_id -> registry
+    vendored_registries: BTreeMap<String, VendoredRegistry>,
+    // The config file relative to the root.
+    config_path: String,
+    // What is picked up from the config, to be shown in comments.
+    config_notes: Vec<String>,
+    toolchain: Option<Toolchain>,
+}
+
+struct Toolchain {
+    // The toolchain file relative to the root.
+    path: &'static str,
+    channel: String,
+    // Use fenix rather than rust-overlay.
+    fenix: bool,
+}
+
+impl Toolchain {
+    /// The fenix toolchain following a named channel, which needs no hash.
+    fn fenix_channel(&self) -> Option<&'static str> {
+        match &*self.channel {
+            "stable" => Some("stable"),
+            "beta" => Some("beta"),
+            // fenix's nightly toolchains are named by profiles.
+            "nightly" => Some("default"),
+            _ => None,
+        }
+    }
+}
+
+struct VendoredRegistry {
+    // Relative path to the root.
+    path: String,
+    // The Nix expression of overrides for `mkIndex`.
+    overrides: &'static str,
 }
 
 mod filters {
@@ -232,6 +361,15 @@ mod filters {
         Ok(s.replace('\\', "\\\\").replace('"', "\\\""))
     }
 
+    pub fn nix_path(s: &str) -> askama::Result<String> {
+        let is_path_char = |c: char| c.is_ascii_alphanumeric() || "._-+/".contains(c);
+        if s.chars().all(is_path_char) && !s.ends_with('/') {
+            Ok(format!("./{}", s))
+        } else {
+            Ok(format!("(./. + \"/{}\")", nix_escape(s)?))
+        }
+    }
+
     pub fn ident_or_str(s: &str) -> askama::Result<String> {
         const KEYWORDS: &[&str] = &[
             "if", "then", "else", "assert", "with", "let", "in", "rec", "inherit", "or",
@@ -248,6 +386,91 @@ mod filters {
 }
 
 impl FlakeTemplate {
+    fn add_vendored_registry(&mut self, root: &Path, path: &Path) -> Result<()> {
+        let dir = path.canonicalize()?;
+        let rel_path = dir
+            .strip_prefix(root)
+            .ok()
+            .and_then(|p| p.to_str())
+            .filter(|p| !p.is_empty())
+            .context("Vendored registry is not inside the project")?;
+        let config = fs::read_to_string(dir.join("config.json"))?;
+        let config = serde_json::from_str::<serde_json::Value>(&config)?;
+        let source = config
+            .get("source")
+            .and_then(|v| v.as_str())
+            .unwrap_or(CRATES_IO_INDEX);
+        // It replaces the flake input of the registry, if any.
+        self.registries.remove(source);
+        self.vendored_registries.insert(
+            source.to_owned(),
+            VendoredRegistry {
+                path: rel_path.to_owned(),
+                overrides: if source == CRATES_IO_INDEX {
+                    "nocargo.lib.${system}.cratesIoOverrides"
+                } else {
+                    "{}"
+                },
+            },
+        );
+        Ok(())
+    }
+
+    fn pick_up_config(&mut self, root: &Path, config: &CargoConfig) -> Result<()> {
+        if !config.env.is_empty() {
+            self.config_notes
+                .push(format!("[env] {}", config.env.join(", ")));
+        }
+        for (name, replace_with) in &config.replacements {
+            let note = match config.source(replace_with)? {
+                SourceKind::Registry(url) => {
+                    if url != CRATES_IO_INDEX {
+                        let flake_ref = git_url_to_flake_ref(url, None, None)?;
+                        self.registries.insert(url.into(), flake_ref);
+                    }
+                    format!("Source `{}` is replaced by registry {}", name, url)
+                }
+                SourceKind::Directory(path) => {
+                    let dir = root
+                        .join(path)
+                        .canonicalize()
+                        .with_context(|| format!("Failed to locate vendored directory {}", path))?;
+                    let rel_path = dir
+                        .strip_prefix(root)
+                        .ok()
+                        .and_then(|p| p.to_str())
+                        .filter(|p| !p.is_empty())
+                        .with_context(|| {
+                            format!("Vendored directory is not inside the project: {}", path)
+                        })?;
+                    self.vendor_dirs
+                        .insert(replace_with.clone(), rel_path.to_owned());
+                    format!(
+                        "Source `{}` is replaced by vendored directory {}",
+                        name, rel_path
+                    )
+                }
+                SourceKind::LocalRegistry(path) => {
+                    eprintln!(
+                        "warning: Source `{}` is replaced by directory {:?}, which should be defined in `extraRegistries.{}`",
+                        name, path, replace_with,
+                    );
+                    format!(
+                        "Source `{}` is replaced by `{}` at {}",
+                        name, replace_with, path
+                    )
+                }
+            };
+            self.config_notes.push(note);
+        }
+        for (registry, deps) in &config.patch {
+            let names = deps.keys().map(|s| &**s).collect::<Vec<_>>();
+            self.config_notes
+                .push(format!("[patch.{}] {}", registry, names.join(", ")));
+        }
+        Ok(())
+    }
+
     fn check_dependency(
         &mut self,
         dep: &Dependency,
@@ -305,6 +528,308 @@ fn get_workspace_members(root: &Path, members: &[impl AsRef<str>]) -> Result<Vec
     Ok(ret)
 }
 
+fn read_toml(path: &Path) -> Result<toml::Value> {
+    let content = read_to_string(path)?;
+    Ok(toml::from_str(&content)?)
+}
+
+/// Load a `Cargo.toml`, with `workspace = true` fields and dependencies inherited from
+/// the workspace root manifest, if any.
+fn load_manifest(path: &Path, workspace: Option<&InheritableWorkspace>) -> Result<Manifest> {
+    let mut value = read_toml(path)?;
+    if let Some(workspace) = workspace {
+        workspace.inherit(&mut value)?;
+    }
+    let mut manifest: Manifest = value.try_into()?;
+    manifest.complete_from_path(path)?;
+    Ok(manifest)
+}
+
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The inheritable parts of `[workspace]` in the workspace root manifest.
+// https://doc.rust-lang.org/cargo/reference/workspaces.html#the-package-table
+// https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#inheriting-a-dependency-from-a-workspace
+#[derive(Debug)]
+struct InheritableWorkspace {
+    root: PathBuf,
+    package: toml::value::Table,
+    dependencies: toml::value::Table,
+}
+
+impl InheritableWorkspace {
+    fn from_root_manifest(root: &Path, manifest: &toml::Value) -> Option<Self> {
+        let ws = manifest.get("workspace")?;
+        let get_table = |key: &str| {
+            ws.get(key)
+                .and_then(|v| v.as_table())
+                .cloned()
+                .unwrap_or_default()
+        };
+        Some(Self {
+            root: root.to_owned(),
+            package: get_table("package"),
+            dependencies: get_table("dependencies"),
+        })
+    }
+
+    fn is_inherited(value: &toml::Value) -> bool {
+        value.get("workspace").and_then(|v| v.as_bool()) == Some(true)
+    }
+
+    fn inherit(&self, manifest: &mut toml::Value) -> Result<()> {
+        if let Some(pkg) = manifest.get_mut("package").and_then(|v| v.as_table_mut()) {
+            for (field, value) in pkg.iter_mut() {
+                if Self::is_inherited(value) {
+                    *value = self.package.get(field).cloned().with_context(|| {
+                        format!(
+                            "`package.{}` is inherited but not defined in `[workspace.package]`",
+                            field,
+                        )
+                    })?;
+                }
+            }
+        }
+
+        self.inherit_dependency_tables(manifest)?;
+        if let Some(targets) = manifest.get_mut("target").and_then(|v| v.as_table_mut()) {
+            for (_, target) in targets.iter_mut() {
+                self.inherit_dependency_tables(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn inherit_dependency_tables(&self, table: &mut toml::Value) -> Result<()> {
+        for &key in DEPENDENCY_TABLES {
+            if let Some(deps) = table.get_mut(key).and_then(|v| v.as_table_mut()) {
+                for (name, dep) in deps.iter_mut() {
+                    self.inherit_dependency(name, dep)
+                        .with_context(|| format!("In dependency {:?}", name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn inherit_dependency(&self, name: &str, dep: &mut toml::Value) -> Result<()> {
+        if !Self::is_inherited(dep) {
+            return Ok(());
+        }
+        let mut inherited = match self.dependencies.get(name) {
+            Some(toml::Value::String(req)) => {
+                let mut detail = toml::value::Table::new();
+                detail.insert("version".into(), req.clone().into());
+                detail
+            }
+            Some(toml::Value::Table(detail)) => detail.clone(),
+            Some(_) => bail!("Invalid dependency in `[workspace.dependencies]`"),
+            None => bail!("Inherited but not defined in `[workspace.dependencies]`"),
+        };
+
+        // Paths in `[workspace.dependencies]` are relative to the workspace root.
+        if let Some(toml::Value::String(path)) = inherited.get_mut("path") {
+            let abs_path = self.root.join(&*path);
+            *path = abs_path
+                .to_str()
+                .with_context(|| {
+                    format!("Non UTF-8 path is not supported: {}", abs_path.display())
+                })?
+                .to_owned();
+        }
+
+        // Only `features` and `optional` are allowed along with `workspace = true`.
+        // `features` are additive, and `default-features = true` re-enables the default features.
+        if let Some(features) = dep.get("features").and_then(|v| v.as_array()) {
+            match inherited
+                .entry("features")
+                .or_insert_with(|| toml::Value::Array(Vec::new()))
+            {
+                toml::Value::Array(inherited_features) => {
+                    inherited_features.extend(features.iter().cloned())
+                }
+                _ => bail!("Invalid `features` in `[workspace.dependencies]`"),
+            }
+        }
+        if let Some(optional) = dep.get("optional") {
+            inherited.insert("optional".into(), optional.clone());
+        }
+        // Both `default-features` and `default_features` are accepted, normalize to the former.
+        if let Some(default_features) = inherited.remove("default_features") {
+            inherited
+                .entry("default-features")
+                .or_insert(default_features);
+        }
+        let member_default_features = dep
+            .get("default-features")
+            .or_else(|| dep.get("default_features"))
+            .and_then(|v| v.as_bool());
+        if member_default_features == Some(true) {
+            inherited.insert("default-features".into(), true.into());
+        }
+
+        *dep = toml::Value::Table(inherited);
+        Ok(())
+    }
+}
+
+/// The parts of `.cargo/config.toml` in the project root which nocargo understands.
+// https://doc.rust-lang.org/cargo/reference/config.html
+#[derive(Debug, Default)]
+struct CargoConfig {
+    path: String,
+    env: Vec<String>,
+    sources: BTreeMap<String, toml::Value>,
+    // (source, the final source replacing it)
+    replacements: Vec<(String, String)>,
+    patch: PatchSet,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SourceKind<'a> {
+    Registry(&'a str),
+    Directory(&'a str),
+    LocalRegistry(&'a str),
+}
+
+impl CargoConfig {
+    fn load(root: &Path) -> Result<Self> {
+        // Like cargo, the legacy one is preferred if both exist.
+        let path = [".cargo/config", ".cargo/config.toml"]
+            .into_iter()
+            .find(|path| root.join(path).is_file());
+        let path = match path {
+            Some(path) => path,
+            None => {
+                return Ok(Self {
+                    path: ".cargo/config.toml".into(),
+                    ..Self::default()
+                })
+            }
+        };
+        let value = read_toml(&root.join(path))?;
+        Self::from_value(path, value)
+    }
+
+    fn from_value(path: &str, mut value: toml::Value) -> Result<Self> {
+        let mut take = |key: &str| {
+            value
+                .as_table_mut()
+                .and_then(|table| table.remove(key))
+                .map(|v| v.try_into::<BTreeMap<String, toml::Value>>())
+                .transpose()
+                .with_context(|| format!("Invalid `[{}]`", key))
+        };
+        let env = take("env")?.unwrap_or_default();
+        let sources = take("source")?.unwrap_or_default();
+        let patch = match take("patch")? {
+            Some(patch) => toml::Value::from(patch)
+                .try_into()
+                .context("Invalid `[patch]`")?,
+            None => PatchSet::default(),
+        };
+
+        let mut config = Self {
+            path: path.into(),
+            env: env.into_keys().collect(),
+            sources,
+            replacements: Vec::new(),
+            patch,
+        };
+        for name in config.sources.keys() {
+            if let Some(replace_with) = config.replacement_of(name)? {
+                config.replacements.push((name.clone(), replace_with));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Follow the chain of `replace-with` of a source, and return the final one.
+    fn replacement_of(&self, name: &str) -> Result<Option<String>> {
+        let mut chain = vec![name];
+        loop {
+            let next = self
+                .sources
+                .get(*chain.last().unwrap())
+                .and_then(|source| source.get("replace-with"));
+            let next = match next {
+                None => break,
+                Some(next) => next.as_str().context("`replace-with` must be a string")?,
+            };
+            ensure!(
+                !chain.contains(&next),
+                "Cyclic source replacement: {} -> {}",
+                chain.join(" -> "),
+                next,
+            );
+            chain.push(next);
+        }
+        Ok((chain.len() > 1).then(|| chain.last().unwrap().to_string()))
+    }
+
+    fn source(&self, name: &str) -> Result<SourceKind<'_>> {
+        if name == "crates-io" {
+            return Ok(SourceKind::Registry(CRATES_IO_INDEX));
+        }
+        let source = self
+            .sources
+            .get(name)
+            .with_context(|| format!("Source `{}` is not defined", name))?;
+        let get = |key: &str| source.get(key).and_then(|v| v.as_str());
+        if let Some(url) = get("registry") {
+            Ok(SourceKind::Registry(url))
+        } else if let Some(path) = get("directory") {
+            Ok(SourceKind::Directory(path))
+        } else if let Some(path) = get("local-registry") {
+            Ok(SourceKind::LocalRegistry(path))
+        } else {
+            bail!("Unsupported kind of source `{}`: {}", name, source)
+        }
+    }
+}
+
+/// Load the toolchain file of rustup in the project root, and return its name and the channel.
+// https://rust-lang.github.io/rustup/overrides.html#the-toolchain-file
+fn load_toolchain_file(root: &Path) -> Result<Option<(&'static str, String)>> {
+    // Like rustup, the legacy one is preferred if both exist.
+    let mut found = ["rust-toolchain", "rust-toolchain.toml"]
+        .into_iter()
+        .filter(|path| root.join(path).is_file());
+    let path = match found.next() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if let Some(ignored) = found.next() {
+        eprintln!(
+            "warning: Both {} and {} exist, the latter is ignored",
+            path, ignored
+        );
+    }
+    let content = read_to_string(root.join(path))?;
+    let channel =
+        parse_toolchain_file(path, &content).with_context(|| format!("Failed to load {}", path))?;
+    Ok(Some((path, channel)))
+}
+
+fn parse_toolchain_file(path: &str, content: &str) -> Result<String> {
+    // The legacy `rust-toolchain` may contain only the channel name.
+    let value = match toml::from_str::<toml::Value>(content) {
+        Ok(value) => value,
+        Err(_) if !path.ends_with(".toml") => return Ok(content.trim().to_owned()),
+        Err(err) => return Err(err.into()),
+    };
+    let toolchain = value.get("toolchain").context("Missing `[toolchain]`")?;
+    ensure!(
+        toolchain.get("path").is_none(),
+        "Custom toolchains by `toolchain.path` are not supported",
+    );
+    Ok(toolchain
+        .get("channel")
+        .and_then(|v| v.as_str())
+        .context("Missing `toolchain.channel`")?
+        .to_owned())
+}
+
 fn get_all_dependencies(manifest: &Manifest) -> impl Iterator<Item = (&str, &Dependency)> {
     manifest
         .dependencies
@@ -320,7 +845,6 @@ fn get_all_dependencies(manifest: &Manifest) -> impl Iterator<Item = (&str, &Dep
         .map(|(name, dep)| (&**name, dep))
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Products {
     library: bool,
@@ -328,6 +852,9 @@ struct Products {
     bench: bool,
     test: bool,
     example: bool,
+    /// Targets only built when their `required-features` are enabled,
+    /// as `(kind, name, required_features)`.
+    conditional: Vec<(&'static str, String, Vec<String>)>,
 }
 
 impl Products {
@@ -342,13 +869,17 @@ impl Products {
             if !decls.is_empty() {
                 return Ok(true);
             }
-            if allow_discover {
+            if !allow_discover {
                 return Ok(false);
             }
-            if matches!(extra_path, Some(p) if Path::new(p).is_file()) {
+            if matches!(extra_path, Some(p) if path.join(p).is_file()) {
                 return Ok(true);
             }
-            for ent in read_dir(path.join(convention_dir))? {
+            let dir = path.join(convention_dir);
+            if !dir.is_dir() {
+                return Ok(false);
+            }
+            for ent in read_dir(dir)? {
                 let ent = ent?;
                 let file_type = ent.file_type()?;
                 // `<dir>/*.rs`
@@ -366,12 +897,30 @@ impl Products {
             }
             Ok(false)
         };
+        let conditional = [
+            ("binary", &manifest.bin),
+            ("example", &manifest.example),
+            ("test", &manifest.test),
+            ("bench", &manifest.bench),
+        ]
+        .into_iter()
+        .flat_map(|(kind, decls)| {
+            decls
+                .iter()
+                .filter(|prod| !prod.required_features.is_empty())
+                .map(move |prod| {
+                    let name = prod.name.clone().unwrap_or_default();
+                    (kind, name, prod.required_features.clone())
+                })
+        })
+        .collect();
         Ok(Self {
             library: manifest.lib.is_some() || path.join("src/lib.rs").exists(),
             binary: has_product(&manifest.bin, pkg.autobins, Some("src/main.rs"), "src/bin")?,
-            bench: has_product(&manifest.bin, pkg.autobenches, None, "benches")?,
+            bench: has_product(&manifest.bench, pkg.autobenches, None, "benches")?,
             test: has_product(&manifest.test, pkg.autotests, None, "tests")?,
             example: has_product(&manifest.example, pkg.autoexamples, None, "examples")?,
+            conditional,
         })
     }
 }
@@ -478,6 +1027,11 @@ pub fn git_url_to_flake_ref(
 #[cfg(test)]
 mod tests {
     use super::git_url_to_flake_ref as f;
+    use super::{
+        load_manifest, parse_toolchain_file, CargoConfig, InheritableWorkspace, Products,
+        SourceKind,
+    };
+    use std::path::Path;
 
     #[test]
     fn test_flake_url_schemas() {
@@ -557,4 +1111,192 @@ mod tests {
             "github:foo/bar"
         );
     }
+
+    #[test]
+    fn test_workspace_inheritance() {
+        let root_manifest: toml::Value = toml::from_str(
+            r#"
+            [workspace.package]
+            version = "0.2.0"
+            edition = "2021"
+
+            [workspace.dependencies]
+            foo = "1"
+            bar = { path = "crates/bar", default-features = false, features = ["a"] }
+            qux = { version = "1", default_features = false }
+            "#,
+        )
+        .unwrap();
+        let ws = InheritableWorkspace::from_root_manifest("/ws".as_ref(), &root_manifest).unwrap();
+
+        let mut manifest: toml::Value = toml::from_str(
+            r#"
+            [package]
+            name = "baz"
+            version.workspace = true
+            edition = { workspace = true }
+
+            [dependencies]
+            foo = { workspace = true, optional = true }
+            qux = { workspace = true, default-features = true }
+
+            [build-dependencies]
+            qux = { workspace = true }
+
+            [target."cfg(unix)".dev-dependencies]
+            bar = { workspace = true, features = ["b"] }
+            "#,
+        )
+        .unwrap();
+        ws.inherit(&mut manifest).unwrap();
+
+        let expect: toml::Value = toml::from_str(
+            r#"
+            [package]
+            name = "baz"
+            version = "0.2.0"
+            edition = "2021"
+
+            [dependencies]
+            foo = { version = "1", optional = true }
+            qux = { version = "1", default-features = true }
+
+            [build-dependencies]
+            qux = { version = "1", default-features = false }
+
+            [target."cfg(unix)".dev-dependencies]
+            bar = { path = "/ws/crates/bar", default-features = false, features = ["a", "b"] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest, expect);
+
+        let mut manifest: toml::Value = toml::from_str(
+            r#"
+            [package]
+            name = "baz"
+            license.workspace = true
+            "#,
+        )
+        .unwrap();
+        ws.inherit(&mut manifest).unwrap_err();
+    }
+
+    #[test]
+    fn test_products() {
+        let root = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/test-targets"
+        ));
+        let manifest = load_manifest(&root.join("Cargo.toml"), None).unwrap();
+        let prod = Products::from_path_manifest(root, &manifest).unwrap();
+        assert!(prod.library && prod.test && prod.example && prod.bench);
+        assert!(!prod.binary);
+        assert!(prod.conditional.is_empty());
+
+        let root = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/required-features"
+        ));
+        let manifest = load_manifest(&root.join("Cargo.toml"), None).unwrap();
+        let prod = Products::from_path_manifest(root, &manifest).unwrap();
+        let cond = |kind, name: &str, feat: &str| (kind, name.to_owned(), vec![feat.to_owned()]);
+        assert_eq!(
+            prod.conditional,
+            [
+                cond("binary", "cli", "cli"),
+                cond("example", "cli", "cli"),
+                cond("test", "cli", "cli"),
+                cond("test", "color", "term/color"),
+                cond("test", "unicode", "term/unicode"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cargo_config() {
+        let root = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/cargo-config"
+        ));
+        let config = CargoConfig::load(root).unwrap();
+        assert_eq!(config.path, ".cargo/config.toml");
+        assert_eq!(config.env, ["GREETING", "MANIFEST_PATH"]);
+        assert_eq!(
+            config.patch["crates-io"].keys().collect::<Vec<_>>(),
+            ["semver"]
+        );
+
+        let value = toml::from_str(
+            r#"
+            [source.crates-io]
+            replace-with = "mirror"
+
+            [source.mirror]
+            registry = "https://example.com/index"
+            replace-with = "vendored"
+
+            [source.vendored]
+            directory = "vendor"
+            "#,
+        )
+        .unwrap();
+        let config = CargoConfig::from_value(".cargo/config", value).unwrap();
+        let vendored = || "vendored".to_owned();
+        assert_eq!(
+            config.replacements,
+            [
+                ("crates-io".into(), vendored()),
+                ("mirror".into(), vendored())
+            ]
+        );
+        assert_eq!(
+            config.source("vendored").unwrap(),
+            SourceKind::Directory("vendor")
+        );
+
+        let value = toml::from_str(
+            r#"
+            source.a.replace-with = "b"
+            source.b.replace-with = "a"
+            "#,
+        )
+        .unwrap();
+        CargoConfig::from_value(".cargo/config", value).unwrap_err();
+
+        let root = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/vendor-dir"));
+        let config = CargoConfig::load(root).unwrap();
+        assert_eq!(
+            config.replacements,
+            [("crates-io".into(), "vendored-sources".into())]
+        );
+        assert_eq!(
+            config.source("vendored-sources").unwrap(),
+            SourceKind::Directory("vendor")
+        );
+    }
+
+    #[test]
+    fn test_toolchain_file() {
+        let toml = r#"
+            [toolchain]
+            channel = "nightly-2022-10-01"
+            components = ["rustfmt"]
+        "#;
+        assert_eq!(
+            parse_toolchain_file("rust-toolchain.toml", toml).unwrap(),
+            "nightly-2022-10-01"
+        );
+        assert_eq!(
+            parse_toolchain_file("rust-toolchain", toml).unwrap(),
+            "nightly-2022-10-01"
+        );
+        assert_eq!(
+            parse_toolchain_file("rust-toolchain", "1.64.0\n").unwrap(),
+            "1.64.0"
+        );
+        parse_toolchain_file("rust-toolchain.toml", "1.64.0\n").unwrap_err();
+        parse_toolchain_file("rust-toolchain.toml", "[toolchain]\npath = \"/opt/rust\"\n")
+            .unwrap_err();
+    }
 }
@@ -1,7 +1,11 @@
 use anyhow::Result;
 use clap::Parser;
 
+mod cache_hits;
+mod index_subset;
 mod init;
+mod maint;
+mod vendor;
 
 trait App {
     fn run(self) -> Result<()>;
@@ -11,12 +15,20 @@ trait App {
 #[clap(version, about, long_about = None)]
 enum Args {
     Init(init::Args),
+    IndexSubset(index_subset::Args),
+    Vendor(vendor::Args),
+    Maint(maint::Args),
+    CacheHits(cache_hits::Args),
 }
 
 impl App for Args {
     fn run(self) -> Result<()> {
         match self {
             Self::Init(args) => args.run(),
+            Self::IndexSubset(args) => args.run(),
+            Self::Vendor(args) => args.run(),
+            Self::Maint(args) => args.run(),
+            Self::CacheHits(args) => args.run(),
         }
     }
 }
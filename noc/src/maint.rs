@@ -0,0 +1,26 @@
+use anyhow::Result;
+
+mod popular_crates;
+mod proc_macros;
+
+/// Maintenance tasks of nocargo itself.
+#[derive(clap::Args)]
+pub struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    PopularCrates(popular_crates::Args),
+    ProcMacros(proc_macros::Args),
+}
+
+impl super::App for Args {
+    fn run(self) -> Result<()> {
+        match self.command {
+            Command::PopularCrates(args) => args.run(),
+            Command::ProcMacros(args) => args.run(),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Update the popular crates referenced by `cache/Cargo.toml` from a crates.io database dump.
+///
+/// The dump is the extracted https://static.crates.io/db-dump.tar.gz. Run
+/// `cargo update --manifest-path cache/Cargo.toml` afterwards to refresh the lock file.
+#[derive(clap::Args)]
+pub struct Args {
+    /// The extracted database dump, containing `data/*.csv`.
+    #[clap(long)]
+    dump: PathBuf,
+
+    /// The number of top crates to cache.
+    #[clap(long, default_value = "256")]
+    limit: usize,
+
+    /// Only count downloads after this date, in `YYYY-MM-DD`. The date itself is excluded.
+    #[clap(long)]
+    since: String,
+
+    /// The manifest to update.
+    #[clap(long, default_value = "cache/Cargo.toml")]
+    manifest: PathBuf,
+}
+
+impl crate::App for Args {
+    fn run(self) -> Result<()> {
+        let data_dir = if self.dump.join("data").is_dir() {
+            self.dump.join("data")
+        } else {
+            self.dump.clone()
+        };
+
+        eprintln!("Counting downloads after {}", self.since);
+        let mut downloads = HashMap::<u64, u64>::new();
+        read_csv(
+            &data_dir.join("version_downloads.csv"),
+            ["version_id", "downloads", "date"],
+            |[version_id, count, date]| {
+                if date > &*self.since {
+                    *downloads.entry(version_id.parse()?).or_default() += count.parse::<u64>()?;
+                }
+                Ok(())
+            },
+        )?;
+
+        let mut versions = HashMap::new();
+        read_csv(
+            &data_dir.join("versions.csv"),
+            ["id", "crate_id", "num"],
+            |[id, crate_id, num]| {
+                let id = id.parse::<u64>()?;
+                if downloads.contains_key(&id) {
+                    versions.insert(id, (crate_id.parse::<u64>()?, num.to_owned()));
+                }
+                Ok(())
+            },
+        )?;
+
+        let mut crate_names = HashMap::new();
+        read_csv(&data_dir.join("crates.csv"), ["id", "name"], |[id, name]| {
+            crate_names.insert(id.parse::<u64>()?, name.to_owned());
+            Ok(())
+        })?;
+
+        let mut ranked = downloads
+            .iter()
+            .map(|(id, &count)| {
+                let (crate_id, num) = versions
+                    .get(id)
+                    .with_context(|| format!("Unknown version id {}", id))?;
+                let name = crate_names
+                    .get(crate_id)
+                    .with_context(|| format!("Unknown crate id {}", crate_id))?;
+                Ok((count, name.as_str(), VersMajor::parse(num)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Break ties by names and versions, to be deterministic.
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| (a.1, a.2).cmp(&(b.1, b.2))));
+        let crates = select_top(
+            ranked.into_iter().map(|(_, name, vers)| (name, vers)),
+            self.limit,
+        );
+
+        let content = fs::read_to_string(&self.manifest)
+            .with_context(|| format!("Failed to read {}", self.manifest.display()))?;
+        let content = rewrite_dependencies(&content, &crates)
+            .with_context(|| format!("Invalid {}", self.manifest.display()))?;
+        fs::write(&self.manifest, content)
+            .with_context(|| format!("Failed to write {}", self.manifest.display()))?;
+
+        eprintln!(
+            "Wrote {} crates into {}",
+            crates.len(),
+            self.manifest.display()
+        );
+        Ok(())
+    }
+}
+
+/// Read columns from a CSV file with headers.
+fn read_csv<const N: usize>(
+    path: &Path,
+    columns: [&str; N],
+    mut f: impl FnMut([&str; N]) -> Result<()>,
+) -> Result<()> {
+    (|| -> Result<()> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let headers = rdr.headers()?.clone();
+        let indices = columns
+            .iter()
+            .map(|col| {
+                headers
+                    .iter()
+                    .position(|h| h == *col)
+                    .with_context(|| format!("Missing column `{}`", col))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut record = csv::StringRecord::new();
+        while rdr.read_record(&mut record)? {
+            f(std::array::from_fn(|i| &record[indices[i]]))?;
+        }
+        Ok(())
+    })()
+    .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// The compatible part of a version, which caret requirements merge on.
+/// Eg. `1` for `1.2.3`, `0.2` for `0.2.3` and `0.0.3` for `0.0.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct VersMajor(u64, Option<u64>, Option<u64>);
+
+impl VersMajor {
+    fn parse(s: &str) -> Result<Self> {
+        static RE_SEMVER: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^(\d+)\.(\d+)\.(\d+)(?:[-+].*)?$").unwrap());
+        let cap = RE_SEMVER
+            .captures(s)
+            .with_context(|| format!("Invalid semver: {}", s))?;
+        let [maj, min, pat] = [1, 2, 3].map(|i| cap[i].parse::<u64>());
+        let (maj, min, pat) = (maj?, min?, pat?);
+        Ok(match (maj, min) {
+            (0, 0) => Self(0, Some(0), Some(pat)),
+            (0, _) => Self(0, Some(min), None),
+            _ => Self(maj, None, None),
+        })
+    }
+}
+
+impl fmt::Display for VersMajor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        for part in [self.1, self.2].into_iter().flatten() {
+            write!(f, ".{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+/// Take the first `limit` distinct crates from the ranked list, then sort them by names, and
+/// newer versions first.
+fn select_top<'a>(
+    ranked: impl IntoIterator<Item = (&'a str, VersMajor)>,
+    limit: usize,
+) -> Vec<(&'a str, VersMajor)> {
+    let mut set = BTreeSet::new();
+    for item in ranked {
+        if set.len() == limit {
+            break;
+        }
+        set.insert(item);
+    }
+    let mut crates = set.into_iter().collect::<Vec<_>>();
+    crates.sort_by(|a, b| a.0.cmp(b.0).then(b.1.cmp(&a.1)));
+    crates
+}
+
+/// Replace everything after the `[dependencies]` line with the crates. Duplicated names are
+/// renamed with suffixes, since cargo rejects compatible versions of the same crate.
+fn rewrite_dependencies(content: &str, crates: &[(&str, VersMajor)]) -> Result<String> {
+    let pos = content
+        .lines()
+        .scan(0, |pos, line| {
+            *pos += line.len() + 1;
+            Some((*pos, line))
+        })
+        .find(|(_, line)| line.starts_with("[dependencies]"))
+        .map(|(pos, _)| pos)
+        .context("Missing `[dependencies]`")?;
+    let mut out = content[..pos.min(content.len())].to_owned();
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    let mut last_name = None;
+    let mut idx = 1;
+    for &(name, vers) in crates {
+        if last_name != Some(name) {
+            idx = 1;
+            writeln!(out, "{} = \"{}\"", name, vers).unwrap();
+        } else {
+            idx += 1;
+            writeln!(
+                out,
+                "{}-{} = {{ package = \"{}\", version = \"{}\" }}",
+                name, idx, name, vers
+            )
+            .unwrap();
+        }
+        last_name = Some(name);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vers_major() {
+        let f = |s| VersMajor::parse(s).unwrap().to_string();
+        assert_eq!(f("1.2.3"), "1");
+        assert_eq!(f("0.2.3-alpha.1"), "0.2");
+        assert_eq!(f("0.0.3+build"), "0.0.3");
+        assert!(VersMajor::parse("1.2").is_err());
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let ranked = [
+            ("syn", "1.0.99"),
+            ("syn", "1.0.98"),
+            ("bitflags", "1.3.2"),
+            ("syn", "0.15.44"),
+            ("syn", "2.0.0"),
+            ("libc", "0.2.1"),
+        ]
+        .map(|(name, vers)| (name, VersMajor::parse(vers).unwrap()));
+        let crates = select_top(ranked, 4);
+
+        let content = "[package]\nname = \"cache\"\n\n[dependencies]\nold = \"1\"\n";
+        let got = rewrite_dependencies(content, &crates).unwrap();
+        expect_test::expect![[r#"
+            [package]
+            name = "cache"
+
+            [dependencies]
+            bitflags = "1"
+            syn = "2"
+            syn-2 = { package = "syn", version = "1" }
+            syn-3 = { package = "syn", version = "0.15" }
+        "#]]
+        .assert_eq(&got);
+
+        assert!(rewrite_dependencies("[package]\n", &crates).is_err());
+    }
+}
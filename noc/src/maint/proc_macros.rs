@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use semver::Version;
+
+use crate::vendor::find_crate_files;
+
+/// Update the list of proc-macro crates from `.crate` tarballs.
+///
+/// Entries already in the list are kept, so it can be updated incrementally from only recently
+/// published crates. Crates switching between proc-macro and normal libraries are recorded with
+/// version ranges.
+///
+/// The crates.io database dump does not record target kinds, so tarballs are always required.
+#[derive(clap::Args)]
+pub struct Args {
+    /// The cargo registry cache (eg. `~/.cargo/registry`), or a directory of `.crate` files.
+    #[clap(long)]
+    crates: PathBuf,
+
+    /// The list to update.
+    #[clap(long, default_value = "crates-io-override/proc-macro.nix")]
+    list: PathBuf,
+}
+
+impl crate::App for Args {
+    fn run(self) -> Result<()> {
+        let content = fs::read_to_string(&self.list)
+            .with_context(|| format!("Failed to read {}", self.list.display()))?;
+        let entries =
+            parse_list(&content).with_context(|| format!("Invalid {}", self.list.display()))?;
+
+        let crate_files = find_crate_files(&self.crates)
+            .with_context(|| format!("Failed to find .crate files in {}", self.crates.display()))?;
+        let mut observed = BTreeMap::<String, Vec<(Version, bool)>>::new();
+        let mut errors = 0usize;
+        for paths in crate_files.values() {
+            // Crates with the same file name from different registries are assumed identical.
+            let path = &paths[0];
+            match read_crate_kind(path) {
+                Ok((name, version, is_proc_macro)) => observed
+                    .entry(name)
+                    .or_default()
+                    .push((version, is_proc_macro)),
+                Err(err) => {
+                    eprintln!("warning: Skipped {}: {:#}", path.display(), err);
+                    errors += 1;
+                }
+            }
+        }
+
+        let entries = merge(entries, observed);
+        fs::write(&self.list, render_list(&entries))
+            .with_context(|| format!("Failed to write {}", self.list.display()))?;
+
+        let ranged = entries
+            .values()
+            .filter(|entry| matches!(entry, Entry::Ranges(_)))
+            .count();
+        eprintln!(
+            "Scanned {} crates ({} skipped), wrote {} proc-macro crates ({} with version ranges) into {}",
+            crate_files.len(),
+            errors,
+            entries.len(),
+            ranged,
+            self.list.display(),
+        );
+        Ok(())
+    }
+}
+
+/// An entry in the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    /// All versions are proc-macro crates.
+    Always,
+    /// Versions in any of `[from, until)` are proc-macro crates. `None` means unbounded.
+    Ranges(Vec<(Option<Version>, Option<Version>)>),
+}
+
+/// Read the name, version and whether it is a proc-macro crate from a `.crate` tarball.
+fn read_crate_kind(path: &Path) -> Result<(String, Version, bool)> {
+    let content = fs::read(path)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(&*content));
+    for ent in archive.entries()? {
+        let mut ent = ent?;
+        // The manifest is at `<name>-<version>/Cargo.toml`.
+        if ent.path()?.iter().count() != 2 || !ent.path()?.ends_with("Cargo.toml") {
+            continue;
+        }
+        let mut manifest = String::new();
+        ent.read_to_string(&mut manifest)?;
+        return manifest_kind(&toml::from_str(&manifest)?);
+    }
+    bail!("Missing Cargo.toml")
+}
+
+fn manifest_kind(manifest: &toml::Value) -> Result<(String, Version, bool)> {
+    // Ancient crates use `[project]`.
+    let package = manifest
+        .get("package")
+        .or_else(|| manifest.get("project"))
+        .context("Missing `[package]`")?;
+    let get_str = |key: &str| package.get(key).and_then(|v| v.as_str());
+    let name = get_str("name").context("Missing `package.name`")?;
+    let version = get_str("version").context("Missing `package.version`")?;
+    let lib = manifest.get("lib");
+    let is_proc_macro = ["proc-macro", "proc_macro"].iter().any(|key| {
+        lib.and_then(|lib| lib.get(key))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    });
+    Ok((name.to_owned(), version.parse()?, is_proc_macro))
+}
+
+static RE_STRING_ENTRY: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^"([^"]+)"$"#).unwrap());
+static RE_RANGES_ENTRY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\{ name = "([^"]+)"; ranges = \[ ((?:\[ (?:"[^"]+"|null) (?:"[^"]+"|null) \] )*)\]; \}$"#)
+        .unwrap()
+});
+static RE_RANGE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\[ (?:"([^"]+)"|null) (?:"([^"]+)"|null) \]"#).unwrap());
+
+/// Parse the list written by `render_list`.
+fn parse_list(content: &str) -> Result<BTreeMap<String, Entry>> {
+    let mut lines = content.lines();
+    ensure!(lines.next() == Some("["), "Expecting `[` at the first line");
+    let mut ret = BTreeMap::new();
+    for line in lines {
+        let (name, entry) = if line == "]" {
+            break;
+        } else if let Some(cap) = RE_STRING_ENTRY.captures(line) {
+            (cap[1].to_owned(), Entry::Always)
+        } else if let Some(cap) = RE_RANGES_ENTRY.captures(line) {
+            let ranges = RE_RANGE
+                .captures_iter(&cap[2])
+                .map(|range| {
+                    let bound = |i| range.get(i).map(|m| m.as_str().parse()).transpose();
+                    Ok((bound(1)?, bound(2)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            (cap[1].to_owned(), Entry::Ranges(ranges))
+        } else {
+            bail!("Unrecognized line: {}", line);
+        };
+        ensure!(
+            ret.insert(name.clone(), entry).is_none(),
+            "Duplicated entry {}",
+            name
+        );
+    }
+    Ok(ret)
+}
+
+fn render_list(entries: &BTreeMap<String, Entry>) -> String {
+    let mut out = String::from("[\n");
+    for (name, entry) in entries {
+        match entry {
+            Entry::Always => writeln!(out, "{:?}", name).unwrap(),
+            Entry::Ranges(ranges) => {
+                let bound = |v: &Option<Version>| match v {
+                    Some(v) => format!("\"{}\"", v),
+                    None => "null".to_owned(),
+                };
+                write!(out, "{{ name = {:?}; ranges = [ ", name).unwrap();
+                for (from, until) in ranges {
+                    write!(out, "[ {} {} ] ", bound(from), bound(until)).unwrap();
+                }
+                out.push_str("]; }\n");
+            }
+        }
+    }
+    out.push_str("]\n");
+    out
+}
+
+/// Merge observed crate kinds into existing entries.
+///
+/// Existing entries are trusted for versions not observed. An observed version of different kind
+/// starts a new range from it, until the next version where the kind changes.
+fn merge(
+    mut entries: BTreeMap<String, Entry>,
+    observed: BTreeMap<String, Vec<(Version, bool)>>,
+) -> BTreeMap<String, Entry> {
+    for (name, mut versions) in observed {
+        versions.sort();
+
+        // The kind before all change points, and change points with the kind since them.
+        let (initial, mut changes) = match entries.get(&name) {
+            None => (versions[0].1, BTreeMap::new()),
+            Some(Entry::Always) => (true, BTreeMap::new()),
+            Some(Entry::Ranges(ranges)) => {
+                let initial = matches!(ranges.first(), Some((None, _)));
+                let mut changes = BTreeMap::new();
+                for (from, until) in ranges {
+                    changes.extend(from.clone().map(|v| (v, true)));
+                    changes.extend(until.clone().map(|v| (v, false)));
+                }
+                (initial, changes)
+            }
+        };
+
+        for (version, kind) in versions {
+            let cur = changes
+                .range(..=&version)
+                .next_back()
+                .map_or(initial, |(_, &kind)| kind);
+            if cur != kind {
+                changes.insert(version, kind);
+            }
+        }
+
+        let mut ranges = Vec::new();
+        let mut from = if initial { Some(None) } else { None };
+        for (version, kind) in changes {
+            match (kind, from.is_some()) {
+                (true, false) => from = Some(Some(version)),
+                (false, true) => ranges.push((from.take().unwrap(), Some(version))),
+                // Redundant change points.
+                _ => {}
+            }
+        }
+        ranges.extend(from.map(|from| (from, None)));
+
+        match &*ranges {
+            [] => entries.remove(&name),
+            [(None, None)] => entries.insert(name, Entry::Always),
+            _ => entries.insert(name, Entry::Ranges(ranges)),
+        };
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ver(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_list_roundtrip() {
+        let content = r#"[
+"a-derive"
+{ name = "b"; ranges = [ [ "0.5.0" null ] ]; }
+{ name = "c"; ranges = [ [ null "0.2.0" ] [ "1.0.0-rc.1" "1.1.0" ] ]; }
+]
+"#;
+        let entries = parse_list(content).unwrap();
+        assert_eq!(entries["a-derive"], Entry::Always);
+        assert_eq!(
+            entries["c"],
+            Entry::Ranges(vec![
+                (None, Some(ver("0.2.0"))),
+                (Some(ver("1.0.0-rc.1")), Some(ver("1.1.0"))),
+            ])
+        );
+        assert_eq!(render_list(&entries), content);
+
+        assert!(parse_list("[\n\"a\"\n\"a\"\n]\n").is_err());
+        assert!(parse_list("[\nfoo\n]\n").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let entries = parse_list(
+            r#"[
+"always"
+"became-lib"
+{ name = "ranged"; ranges = [ [ "0.5.0" null ] ]; }
+]
+"#,
+        )
+        .unwrap();
+        let observed = [
+            ("always", &[("1.0.0", true)][..]),
+            ("became-lib", &[("0.1.0", false)]),
+            (
+                "ranged",
+                &[("0.4.0", false), ("0.5.3", true), ("0.6.0", false)],
+            ),
+            ("new-macro", &[("0.2.0", true), ("0.1.0", true)]),
+            ("new-lib", &[("0.1.0", false)]),
+            (
+                "new-hack",
+                &[("0.2.0", true), ("0.1.0", false), ("0.3.0", true)],
+            ),
+        ]
+        .iter()
+        .map(|(name, vers)| {
+            let vers = vers.iter().map(|&(v, kind)| (ver(v), kind)).collect();
+            (name.to_string(), vers)
+        })
+        .collect();
+
+        let got = render_list(&merge(entries, observed));
+        expect_test::expect![[r#"
+            [
+            "always"
+            { name = "became-lib"; ranges = [ [ null "0.1.0" ] ]; }
+            { name = "new-hack"; ranges = [ [ "0.2.0" null ] ]; }
+            "new-macro"
+            { name = "ranged"; ranges = [ [ "0.5.0" "0.6.0" ] ]; }
+            ]
+        "#]]
+        .assert_eq(&got);
+    }
+
+    #[test]
+    fn test_manifest_kind() {
+        let manifest = toml::toml! {
+            [package]
+            name = "foo"
+            version = "0.1.0"
+            [lib]
+            proc-macro = true
+        };
+        assert_eq!(
+            manifest_kind(&manifest).unwrap(),
+            ("foo".to_owned(), ver("0.1.0"), true)
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+pub const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Build a local registry from `.crate` files, containing crates referenced by `Cargo.lock`.
+///
+/// The output directory can be used by `mkIndex` in place of the original registry. Crates are
+/// downloaded from `file://` URLs, so the directory must be visible to the Nix builder,
+/// eg. via `extra-sandbox-paths`.
+#[derive(clap::Args)]
+pub struct Args {
+    /// The cargo registry cache (eg. `~/.cargo/registry`), or a directory of `.crate` files.
+    #[clap(long)]
+    from: PathBuf,
+
+    /// The output directory.
+    #[clap(long)]
+    out: PathBuf,
+
+    /// The path to `Cargo.lock`.
+    #[clap(long, default_value = "Cargo.lock")]
+    lock: PathBuf,
+
+    /// The registry whose crates are vendored.
+    #[clap(long, default_value = CRATES_IO_INDEX)]
+    registry: String,
+}
+
+impl super::App for Args {
+    fn run(self) -> Result<()> {
+        let lock = LockedPackage::load_all(&self.lock)?;
+        let source = format!("registry+{}", self.registry);
+        let locked = lock
+            .iter()
+            .filter(|pkg| pkg.source.as_deref() == Some(&*source))
+            .collect::<Vec<_>>();
+
+        let crate_files = find_crate_files(&self.from)
+            .with_context(|| format!("Failed to find .crate files in {}", self.from.display()))?;
+
+        fs::create_dir_all(&self.out)?;
+        let out = self.out.canonicalize()?;
+        let dl_dir = out.join("crates");
+
+        let mut index_files = BTreeMap::<PathBuf, Vec<String>>::new();
+        let mut missing = Vec::new();
+        for pkg in &locked {
+            let checksum = pkg.checksum.as_deref().with_context(|| {
+                format!(
+                    "Missing checksum of {} {} in Cargo.lock",
+                    pkg.name, pkg.version
+                )
+            })?;
+            let file_name = format!("{}-{}.crate", pkg.name, pkg.version);
+            let found = match crate_files.get(&file_name) {
+                Some(paths) => find_by_checksum(paths, checksum)?,
+                None => None,
+            };
+            let (path, content) = match found {
+                Some(found) => found,
+                None => {
+                    missing.push(format!("{} {}", pkg.name, pkg.version));
+                    continue;
+                }
+            };
+
+            let manifest = read_packaged_manifest(&content, &pkg.name, &pkg.version)
+                .with_context(|| format!("Failed to read Cargo.toml from {}", path.display()))?;
+            let entry = index_entry(&manifest, checksum)
+                .with_context(|| format!("Invalid Cargo.toml in {}", path.display()))?;
+
+            let dl_path = dl_dir.join(&pkg.name).join(&pkg.version).join("download");
+            fs::create_dir_all(dl_path.parent().unwrap())?;
+            fs::write(&dl_path, &content)
+                .with_context(|| format!("Failed to write {}", dl_path.display()))?;
+            index_files
+                .entry(index_path(&pkg.name))
+                .or_default()
+                .push(entry.to_string());
+        }
+        ensure!(
+            missing.is_empty(),
+            "{} crates are not found in {}, or their checksums mismatch:\n{}",
+            missing.len(),
+            self.from.display(),
+            missing.join("\n"),
+        );
+
+        for (path, lines) in &index_files {
+            let path = out.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, lines.join("\n") + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        let dl = format!(
+            "file://{}",
+            dl_dir.to_str().context("Non UTF-8 output path")?
+        );
+        // `source` is not known by cargo. It tells `noc init` which registry this replaces.
+        let config = json!({ "dl": dl, "source": self.registry });
+        fs::write(out.join("config.json"), format!("{:#}\n", config))?;
+
+        eprintln!(
+            "Vendored {} crates into {}",
+            locked.len(),
+            self.out.display()
+        );
+        Ok(())
+    }
+}
+
+/// A `[[package]]` of `Cargo.lock`.
+#[derive(Debug)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub source: Option<String>,
+    pub checksum: Option<String>,
+}
+
+impl LockedPackage {
+    pub fn load_all(path: &Path) -> Result<Vec<Self>> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lock = toml::from_str::<toml::Value>(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Self::from_lock(&lock).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    fn from_lock(lock: &toml::Value) -> Result<Vec<Self>> {
+        let get =
+            |pkg: &toml::Value, key: &str| pkg.get(key).and_then(|v| v.as_str()).map(String::from);
+        let empty = Vec::new();
+        let pkgs = match lock.get("package") {
+            None => &empty,
+            Some(pkgs) => pkgs.as_array().context("`package` must be an array")?,
+        };
+        pkgs.iter()
+            .map(|pkg| {
+                let name = get(pkg, "name").context("Missing `name` of package")?;
+                let version = get(pkg, "version")
+                    .with_context(|| format!("Missing `version` of package {}", name))?;
+                let source = get(pkg, "source");
+                // v1 stores checksums in `[metadata]`.
+                let checksum = get(pkg, "checksum").or_else(|| {
+                    let key = format!("checksum {} {} ({})", name, version, source.as_deref()?);
+                    lock.get("metadata")?
+                        .get(&key)?
+                        .as_str()
+                        .filter(|&c| c != "<none>")
+                        .map(String::from)
+                });
+                Ok(Self {
+                    name,
+                    version,
+                    source,
+                    checksum,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The path of a crate in the index.
+/// https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
+pub fn index_path(name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => ["1", &name].iter().collect(),
+        2 => ["2", &name].iter().collect(),
+        3 => ["3", &name[..1], &name].iter().collect(),
+        _ => [&name[..2], &name[2..4], &name].iter().collect(),
+    }
+}
+
+/// Collect `.crate` files by their file names.
+pub(crate) fn find_crate_files(from: &Path) -> Result<BTreeMap<String, Vec<PathBuf>>> {
+    // The cargo registry cache stores crates in `cache/<registry>/`.
+    let dirs = if from.join("cache").is_dir() {
+        fs::read_dir(from.join("cache"))?
+            .map(|ent| Ok(ent?.path()))
+            .filter(|path| path.as_ref().map_or(true, |path: &PathBuf| path.is_dir()))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![from.to_owned()]
+    };
+
+    let mut ret = BTreeMap::<_, Vec<_>>::new();
+    for dir in dirs {
+        for ent in fs::read_dir(&dir)? {
+            let path = ent?.path();
+            match path.file_name().and_then(|s| s.to_str()) {
+                Some(name) if name.ends_with(".crate") => {
+                    ret.entry(name.to_owned()).or_default().push(path)
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Find the first `.crate` file matching the checksum, since different registries may have
+/// crates with the same name and version.
+fn find_by_checksum(paths: &[PathBuf], checksum: &str) -> Result<Option<(PathBuf, Vec<u8>)>> {
+    for path in paths {
+        let content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if format!("{:x}", Sha256::digest(&content)) == checksum {
+            return Ok(Some((path.clone(), content)));
+        }
+    }
+    Ok(None)
+}
+
+/// Read the normalized `Cargo.toml` from a `.crate` tarball.
+fn read_packaged_manifest(content: &[u8], name: &str, version: &str) -> Result<toml::Value> {
+    let expect_path = Path::new(&format!("{}-{}", name, version)).join("Cargo.toml");
+    let mut archive = tar::Archive::new(GzDecoder::new(content));
+    for ent in archive.entries()? {
+        let mut ent = ent?;
+        if *ent.path()? == *expect_path {
+            let mut manifest = String::new();
+            ent.read_to_string(&mut manifest)?;
+            return Ok(toml::from_str(&manifest)?);
+        }
+    }
+    bail!("Missing {}", expect_path.display())
+}
+
+/// Convert a packaged `Cargo.toml` into a line of the registry index.
+/// https://doc.rust-lang.org/cargo/reference/registry-index.html#json-schema
+fn index_entry(manifest: &toml::Value, checksum: &str) -> Result<Value> {
+    let package = manifest.get("package").context("Missing `[package]`")?;
+    let get_str = |key: &str| package.get(key).and_then(|v| v.as_str());
+
+    let mut deps = Vec::new();
+    let targets = manifest
+        .get("target")
+        .and_then(|v| v.as_table())
+        .into_iter()
+        .flatten()
+        .map(|(cfg, table)| (Some(&**cfg), table));
+    for (target, table) in std::iter::once((None, manifest)).chain(targets) {
+        for (kind, keys) in [
+            ("normal", &["dependencies"][..]),
+            ("dev", &["dev-dependencies", "dev_dependencies"]),
+            ("build", &["build-dependencies", "build_dependencies"]),
+        ] {
+            for key in keys {
+                let set = match table.get(key).and_then(|v| v.as_table()) {
+                    Some(set) => set,
+                    None => continue,
+                };
+                for (name, dep) in set {
+                    deps.push(index_dep(name, dep, target, kind)?);
+                }
+            }
+        }
+    }
+
+    let features = match manifest.get("features") {
+        Some(features) => serde_json::to_value(features)?,
+        None => json!({}),
+    };
+    Ok(json!({
+        "name": get_str("name").context("Missing `package.name`")?,
+        "vers": get_str("version").context("Missing `package.version`")?,
+        "deps": deps,
+        "cksum": checksum,
+        "features": features,
+        "yanked": false,
+        "links": get_str("links"),
+        "rust_version": get_str("rust-version"),
+        // Not in crates.io index, but proc macro crates behave differently in dependency resolution.
+        "proc_macro": manifest
+            .get("lib")
+            .and_then(|lib| lib.get("proc-macro").or_else(|| lib.get("proc_macro")))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    }))
+}
+
+fn index_dep(name: &str, dep: &toml::Value, target: Option<&str>, kind: &str) -> Result<Value> {
+    let invalid = || format!("Invalid dependency `{}`", name);
+    let (req, detail) = match dep {
+        toml::Value::String(req) => (&**req, None),
+        toml::Value::Table(detail) => {
+            let req = match detail.get("version") {
+                Some(v) => v.as_str().with_context(invalid)?,
+                None => "*",
+            };
+            (req, Some(detail))
+        }
+        _ => bail!(invalid()),
+    };
+    let get = |key: &str| detail.and_then(|d| d.get(key));
+    let get_bool = |keys: &[&str], default: bool| {
+        keys.iter()
+            .find_map(|key| get(key))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(default)
+    };
+    let features = match get("features") {
+        Some(features) => serde_json::to_value(features)?,
+        None => json!([]),
+    };
+    let mut ret = json!({
+        "name": name,
+        "req": req,
+        "features": features,
+        "optional": get_bool(&["optional"], false),
+        "default_features": get_bool(&["default-features", "default_features"], true),
+        "target": target,
+        "kind": kind,
+        "registry": get("registry-index").and_then(|v| v.as_str()),
+    });
+    // The original name of a renamed dependency.
+    if let Some(package) = get("package").and_then(|v| v.as_str()) {
+        ret["package"] = package.into();
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_path() {
+        let p = |s: &str| s.split('/').collect::<PathBuf>();
+        assert_eq!(index_path("a"), p("1/a"));
+        assert_eq!(index_path("ab"), p("2/ab"));
+        assert_eq!(index_path("abc"), p("3/a/abc"));
+        assert_eq!(index_path("Serde_json"), p("se/rd/serde_json"));
+    }
+
+    #[test]
+    fn test_index_entry() {
+        let manifest = toml::from_str(
+            r#"
+            [package]
+            name = "foo"
+            version = "0.1.0"
+            links = "foo"
+            rust-version = "1.60"
+
+            [lib]
+            proc-macro = true
+
+            [features]
+            default = ["std"]
+            std = []
+
+            [dependencies.bar]
+            version = "1"
+            optional = true
+            default-features = false
+            package = "bar-rs"
+
+            [target."cfg(unix)".build-dependencies]
+            cc = "1"
+            "#,
+        )
+        .unwrap();
+        let got = index_entry(&manifest, "123").unwrap();
+        let expect = json!({
+            "name": "foo",
+            "vers": "0.1.0",
+            "deps": [
+                {
+                    "name": "bar",
+                    "req": "1",
+                    "features": [],
+                    "optional": true,
+                    "default_features": false,
+                    "target": null,
+                    "kind": "normal",
+                    "registry": null,
+                    "package": "bar-rs",
+                },
+                {
+                    "name": "cc",
+                    "req": "1",
+                    "features": [],
+                    "optional": false,
+                    "default_features": true,
+                    "target": "cfg(unix)",
+                    "kind": "build",
+                    "registry": null,
+                },
+            ],
+            "cksum": "123",
+            "features": { "default": ["std"], "std": [] },
+            "yanked": false,
+            "links": "foo",
+            "rust_version": "1.60",
+            "proc_macro": true,
+        });
+        assert_eq!(got, expect);
+    }
+
+    #[test]
+    fn test_lock_checksums() {
+        let lock = toml::from_str(
+            r#"
+            [[package]]
+            name = "foo"
+            version = "0.1.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "bar"
+            version = "0.2.0"
+
+            [metadata]
+            "checksum foo 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)" = "123"
+            "#,
+        )
+        .unwrap();
+        let pkgs = LockedPackage::from_lock(&lock).unwrap();
+        assert_eq!(pkgs[0].checksum.as_deref(), Some("123"));
+        assert_eq!(pkgs[1].source, None);
+        assert_eq!(pkgs[1].checksum, None);
+    }
+}
@@ -14,6 +14,19 @@
       inputs.nixpkgs.follows = "nixpkgs";
       # inputs.registry-crates-io.follows = "registry-crates-io";
     };
+    {%- if let Some(toolchain) = toolchain %}
+    {%- if toolchain.fenix %}
+    fenix = {
+      url = "github:nix-community/fenix";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    {%- else %}
+    rust-overlay = {
+      url = "github:oxalica/rust-overlay";
+      inputs.nixpkgs.follows = "nixpkgs";
+    };
+    {%- endif %}
+    {%- endif %}
     # Optionally, you can override crates.io index to get cutting-edge packages.
     # registry-crates-io = { url = "github:rust-lang/crates.io-index"; flake = false; };
     {%- for (_, flake_ref) in registries %}
@@ -27,15 +40,49 @@
   outputs = { nixpkgs, flake-utils, nocargo, ... }@inputs:
     flake-utils.lib.eachSystem [ "x86_64-linux" "aarch64-linux" ] (system:
       let
+        {%- if let Some(toolchain) = toolchain %}
+        # Toolchain `{{ toolchain.channel }}` pinned by {{ toolchain.path }}.
+        {%- if !toolchain.fenix %}
+        rustc = (import nixpkgs {
+          inherit system;
+          overlays = [ inputs.rust-overlay.overlays.default ];
+        }).rust-bin.fromRustupToolchainFile {{ toolchain.path|nix_path }};
+        {%- else if let Some(channel) = toolchain.fenix_channel() %}
+        rustc = inputs.fenix.packages.${system}.{{ channel }}.toolchain;
+        {%- else %}
+        # NB. Replace the placeholder `fakeSha256` with the hash reported by the first evaluation,
+        # which fails until then.
+        rustc = inputs.fenix.packages.${system}.fromToolchainFile {
+          file = {{ toolchain.path|nix_path }};
+          sha256 = nixpkgs.lib.fakeSha256;
+        };
+        {%- endif %}
+        {%- endif %}
         ws = nocargo.lib.${system}.mkRustPackageOrWorkspace {
           src = ./.;
-          {%- if !registries.is_empty() %}
+          {%- if toolchain.is_some() %}
+          inherit rustc;
+          {%- endif %}
+          {%- for note in config_notes %}
+          {%- if loop.first %}
+
+          # Picked up from {{ config_path }}:
+          {%- endif %}
+          # - {{ note }}
+          {%- endfor %}
+          {%- if !registries.is_empty() || !vendor_dirs.is_empty() || !vendored_registries.is_empty() %}
 
-          # Referenced external registries other than crates.io.
+          # Referenced external registries other than crates.io, and vendored ones.
           extraRegistries = {
             {%- for (source_id, _) in registries %}
             "{{ source_id|nix_escape }}" = nocargo.lib.${system}.mkIndex inputs.registry-{{ loop.index }} {};
             {%- endfor %}
+            {%- for (name, path) in vendor_dirs %}
+            "{{ name|nix_escape }}" = nocargo.lib.${system}.mkVendorDir {{ path|nix_path }} {};
+            {%- endfor %}
+            {%- for (source_id, reg) in vendored_registries %}
+            "{{ source_id|nix_escape }}" = nocargo.lib.${system}.mkIndex {{ reg.path|nix_path }} {{ reg.overrides }};
+            {%- endfor %}
           };
           {%- endif %}
           {%- if !git_srcs.is_empty() %}
@@ -49,17 +96,41 @@
           {%- endif %}
         };
       in rec {
+        {%- if let Some((_, prod)) = main_pkg %}
+        {%- for (kind, name, features) in prod.conditional %}
+        {%- if loop.first %}
+        # These targets are only built when their required features are enabled.
+        {%- endif %}
+        # - {{ kind }} `{{ name }}`: {{ features|join(", ") }}
+        {%- endfor %}
+        {%- endif %}
         {%- if is_workspace %}
         packages = {% if let Some((pkg_name, prod)) = main_pkg %}{
           default = packages.{{ pkg_name|ident_or_str }}{% if prod.binary %}.bin{% endif %};
+          {%- if prod.example %}
+          {{ pkg_name|ident_or_str }}-examples = ws.dev.{{ pkg_name|ident_or_str }}.examples;
+          {%- endif %}
+          {%- if prod.bench %}
+          {{ pkg_name|ident_or_str }}-benches = ws.release.{{ pkg_name|ident_or_str }}.benches;
+          {%- endif %}
         } // {% endif %}ws.release
           // nixpkgs.lib.mapAttrs' (name: value: { name = "${name}-dev"; inherit value; }) ws.dev;
+        checks = nixpkgs.lib.mapAttrs (name: value: value.check) ws.test;
         {%- else if let Some((pkg_name, prod)) = main_pkg %}
         packages = {
           default = packages.{{ pkg_name|ident_or_str }};
           {{ pkg_name|ident_or_str }} = ws.release.{{ pkg_name|ident_or_str }}{% if prod.binary %}.bin{% endif %};
           {{ pkg_name|ident_or_str }}-dev = ws.dev.{{ pkg_name|ident_or_str }}{% if prod.binary %}.bin{% endif %};
+          {%- if prod.example %}
+          {{ pkg_name|ident_or_str }}-examples = ws.dev.{{ pkg_name|ident_or_str }}.examples;
+          {%- endif %}
+          {%- if prod.bench %}
+          {{ pkg_name|ident_or_str }}-benches = ws.release.{{ pkg_name|ident_or_str }}.benches;
+          {%- endif %}
         };
+        {%- if prod.library || prod.test %}
+        checks.{{ pkg_name|ident_or_str }} = ws.test.{{ pkg_name|ident_or_str }}.check;
+        {%- endif %}
         {%- endif %}
       });
 }
@@ -256,8 +256,12 @@ def update_proc_macro_crates(db: CratesioDB, *, concurrency: int) -> None:
         ORDER BY crates.name ASC
     ''')
 
-    RE_ITEMS = re.compile(r'"([^"]*)"', re.S)
-    proc_macro_crates: set[str] = set(m[1] for m in RE_ITEMS.finditer(PROC_MACRO_LIST_PATH.read_text()))
+    # Entries with version ranges are maintained by `noc maint proc-macros`, and kept as is.
+    RE_STRING_ENTRY = re.compile(r'^"([^"]+)"$', re.M)
+    RE_RANGES_ENTRY = re.compile(r'^\{ name = "([^"]+)"; .*\}$', re.M)
+    list_text = PROC_MACRO_LIST_PATH.read_text()
+    proc_macro_crates: set[str] = set(m[1] for m in RE_STRING_ENTRY.finditer(list_text))
+    ranged_entries: dict[str, str] = {m[1]: m[0] for m in RE_RANGES_ENTRY.finditer(list_text)}
 
     async def load_or_fetch(sema: asyncio.Semaphore, name: str, version: str) -> None:
         try:
@@ -292,7 +296,7 @@ def update_proc_macro_crates(db: CratesioDB, *, concurrency: int) -> None:
             with open(out_path, 'r') as fin:
                 manifest = toml.load(fin)
             lib = manifest.get('lib', {})
-            if isinstance(lib, dict) and lib.get('proc-macro', False) is True:
+            if isinstance(lib, dict) and lib.get('proc-macro', False) is True and name not in ranged_entries:
                 proc_macro_crates.add(name)
         except (UnicodeDecodeError, toml.TomlDecodeError) as exc:
             print(f'For cached {name}: {exc}', file=sys.stderr)
@@ -315,10 +319,11 @@ def update_proc_macro_crates(db: CratesioDB, *, concurrency: int) -> None:
 
     noisily(f'Writing to {PROC_MACRO_LIST_PATH}')
     with PROC_MACRO_LIST_PATH.open('w') as fout:
+        entries = {name: f'"{name}"' for name in proc_macro_crates} | ranged_entries
         fout.write('[\n')
-        for name in sorted(proc_macro_crates):
+        for name in sorted(entries):
             assert '"' not in name
-            fout.write(f'"{name}"\n')
+            fout.write(f'{entries[name]}\n')
         fout.write(']\n')
 
 def main() -> None:
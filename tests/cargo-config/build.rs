@@ -0,0 +1,7 @@
+use std::{env, path::Path};
+
+fn main() {
+    let path = env::var("MANIFEST_PATH").unwrap();
+    assert!(Path::new(&path).is_absolute());
+    assert!(Path::new(&path).exists());
+}
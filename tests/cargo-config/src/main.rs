@@ -0,0 +1,4 @@
+fn main() {
+    assert!(semver::PATCHED);
+    println!("{}, world!", env!("GREETING"));
+}
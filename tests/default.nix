@@ -1,7 +1,7 @@
 { pkgs, self, inputs, defaultRegistries }:
 let
   inherit (pkgs.lib) mapAttrs attrNames attrValues assertMsg head mapAttrsToList;
-  inherit (self.lib.${pkgs.system}) mkRustPackageOrWorkspace;
+  inherit (self.lib.${pkgs.system}) mkRustPackageOrWorkspace mkVendorDir;
   inherit (self.packages.${pkgs.system}) noc;
 
   git-semver-1-0-0 = builtins.fetchTarball {
@@ -110,6 +110,7 @@ in
     build-deps = ./build-deps;
     build-feature-env-vars = ./build-feature-env-vars;
     cap-lints = ./cap-lints;
+    cargo-config = ./cargo-config;
     crate-names = ./crate-names;
     custom-lib-name = ./custom-lib-name;
     dependency-v1 = ./dependency-v1;
@@ -118,23 +119,108 @@ in
     features = ./features;
     libz-dynamic = ./libz-dynamic;
     libz-static = ./libz-static;
+    link-args = ./link-args;
     lto-fat = ./lto-fat;
     lto-proc-macro = ./lto-proc-macro;
     lto-thin = ./lto-thin;
+    profile-overrides = ./profile-overrides;
+    required-features = ./required-features;
+    rustflags = ./rustflags;
     tokio-app = ./tokio-app;
   } // {
+    vendor-dir = shouldBeHelloWorld (mkRustPackageOrWorkspace {
+      src = ./vendor-dir;
+      extraRegistries.vendored-sources = mkVendorDir ./vendor-dir/vendor {};
+    }).dev.vendor-dir;
+    workspace-inheritance =
+      shouldBeHelloWorld (mkWorkspaceTest ./workspace-inheritance [ "bar" "foo" ]).dev.foo;
+    workspace-inheritance-deps =
+      shouldBeHelloWorld (mkWorkspaceTest ./workspace-inheritance-deps [ "app" "greet" ]).dev.app;
     workspace-inline = mkWorkspaceTest ./workspace-inline [ "bar" "baz" "foo" ];
     workspace-proc-macro-lto = mkWorkspaceTest ./workspace-proc-macro-lto [ "acro" "procm" ];
     workspace-virtual = mkWorkspaceTest ./workspace-virtual [ "bar" "foo" ];
   };
 
+  _1001-checks = {
+    # Targets with disabled `required-features` are skipped.
+    required-features = (mkWorkspaceTest ./required-features [ "required-features" "term" ]).dev.required-features.check;
+    required-features-examples =
+      (mkWorkspaceTest ./required-features [ "required-features" "term" ]).dev.required-features.examples;
+    # `dep/feat` is satisfied by enabled features of dependencies.
+    required-features-dep = let
+      tests = (mkWorkspaceTest ./required-features [ "required-features" "term" ]).dev.required-features.tests;
+    in pkgs.runCommand "required-features-dep" {} ''
+      [[ -e ${tests}/bin/color ]]
+      [[ ! -e ${tests}/bin/unicode ]]
+      touch $out
+    '';
+    # Each kind of target checks symbols defined by its own link args.
+    link-args = (mkWorkspaceTest ./link-args [ "link-args" ]).dev.link-args.check;
+    link-args-examples = let drv = (mkWorkspaceTest ./link-args [ "link-args" ]).dev.link-args.examples; in
+      shouldBeHelloWorld { inherit (drv) name; bin = drv; };
+    link-args-benches = let drv = (mkWorkspaceTest ./link-args [ "link-args" ]).dev.link-args.benches; in
+      shouldBeHelloWorld { inherit (drv) name; bin = drv; };
+    test-targets = (mkWorkspaceTest ./test-targets [ "bar" "foo" ]).dev.foo.check;
+    test-targets-doctest = (mkWorkspaceTest ./test-targets [ "bar" "foo" ]).dev.foo.doctest;
+    test-targets-examples = let drv = (mkWorkspaceTest ./test-targets [ "bar" "foo" ]).dev.foo.examples; in
+      shouldBeHelloWorld { inherit (drv) name; bin = drv; };
+    test-targets-benches = let drv = (mkWorkspaceTest ./test-targets [ "bar" "foo" ]).dev.foo.benches; in
+      shouldBeHelloWorld { inherit (drv) name; bin = drv; };
+  };
+
+  _1002-docs = {
+    workspace-inline = let
+      ws = mkWorkspaceTest ./workspace-inline [ "bar" "baz" "foo" ];
+      doc = ws.dev.baz.doc;
+      workspaceDoc = ws.dev.foo.workspaceDoc;
+    in pkgs.runCommand "workspace-inline-doc" {} ''
+      # Cross-crate links to previously documented members.
+      grep -F 'href="../bar/fn.hello.html"' ${doc}/share/doc/baz/fn.show.html
+      # Merged crate list.
+      grep -F '"bar"' ${doc}/share/doc/crates.js
+      grep -F '"baz"' ${doc}/share/doc/crates.js
+
+      # All members, including `baz` which `foo` doesn't depend on. `foo` has no library.
+      grep -F 'href="../bar/fn.hello.html"' ${workspaceDoc}/share/doc/baz/fn.show.html
+      grep -F '"bar"' ${workspaceDoc}/share/doc/crates.js
+      grep -F '"baz"' ${workspaceDoc}/share/doc/crates.js
+      touch $out
+    '';
+  };
+
+  _1003-debuginfo = let
+    ws = mkWorkspaceTest ./debuginfo [ "debuginfo" ];
+  in {
+    # Debuginfo is kept by default if requested.
+    dev = pkgs.runCommand "debuginfo-dev" {
+      nativeBuildInputs = [ pkgs.binutils ];
+    } ''
+      readelf -S ${ws.dev.debuginfo.bin}/bin/debuginfo | grep -F .debug_info
+      touch $out
+    '';
+    # Stripped, with separated debuginfo in the `debug` output.
+    release = let bin = ws.release.debuginfo.bin; in pkgs.runCommand "debuginfo-release" {
+      nativeBuildInputs = [ pkgs.binutils ];
+    } ''
+      ! readelf -S ${bin}/bin/debuginfo | grep -F .debug_info
+      [[ "$(echo ${bin}/bin/*)" == ${bin}/bin/debuginfo ]]
+      [[ -n "$(find ${bin.debug}/lib/debug/.build-id -name '*.debug')" ]]
+      [[ -e ${bin.debug}/lib/debug/debuginfo.dwp ]]
+      touch $out
+    '';
+  };
+
   _1100-gen-init = mapAttrs mkGenInit {
+    cargo-config = ./cargo-config;
     dependency-v1 = ./dependency-v1;
     dependency-v2 = ./dependency-v2;
     dependency-v3 = ./dependency-v3;
     features = ./features;
+    vendor-dir = ./vendor-dir;
 
     workspace-virtual = ./workspace-virtual;
     workspace-inline = ./workspace-inline;
+    workspace-inheritance = ./workspace-inheritance;
+    workspace-inheritance-deps = ./workspace-inheritance-deps;
   };
 }
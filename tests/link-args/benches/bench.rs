@@ -0,0 +1,15 @@
+extern "C" {
+    static NOCARGO_LINK_ARG: u8;
+    static NOCARGO_LINK_ARG_BENCHES: u8;
+}
+
+fn main() {
+    let addrs = unsafe {
+        [
+            &NOCARGO_LINK_ARG as *const u8 as usize,
+            &NOCARGO_LINK_ARG_BENCHES as *const u8 as usize,
+        ]
+    };
+    assert_eq!(addrs, [1, 6]);
+    println!("Hello, world!");
+}
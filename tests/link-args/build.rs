@@ -0,0 +1,9 @@
+fn main() {
+    // Define absolute symbols, which can only be resolved if the args reach the linker.
+    println!("cargo:rustc-link-arg=-Wl,--defsym=NOCARGO_LINK_ARG=0x1");
+    println!("cargo:rustc-link-arg-bins=-Wl,--defsym=NOCARGO_LINK_ARG_BINS=0x2");
+    println!("cargo:rustc-link-arg-bin=link-args=-Wl,--defsym=NOCARGO_LINK_ARG_BIN=0x3");
+    println!("cargo:rustc-link-arg-tests=-Wl,--defsym=NOCARGO_LINK_ARG_TESTS=0x4");
+    println!("cargo:rustc-link-arg-examples=-Wl,--defsym=NOCARGO_LINK_ARG_EXAMPLES=0x5");
+    println!("cargo:rustc-link-arg-benches=-Wl,--defsym=NOCARGO_LINK_ARG_BENCHES=0x6");
+}
@@ -0,0 +1,17 @@
+extern "C" {
+    static NOCARGO_LINK_ARG: u8;
+    static NOCARGO_LINK_ARG_BINS: u8;
+    static NOCARGO_LINK_ARG_BIN: u8;
+}
+
+fn main() {
+    let addrs = unsafe {
+        [
+            &NOCARGO_LINK_ARG as *const u8 as usize,
+            &NOCARGO_LINK_ARG_BINS as *const u8 as usize,
+            &NOCARGO_LINK_ARG_BIN as *const u8 as usize,
+        ]
+    };
+    assert_eq!(addrs, [1, 2, 3]);
+    println!("Hello, world!");
+}
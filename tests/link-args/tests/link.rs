@@ -0,0 +1,15 @@
+extern "C" {
+    static NOCARGO_LINK_ARG: u8;
+    static NOCARGO_LINK_ARG_TESTS: u8;
+}
+
+#[test]
+fn link_args() {
+    let addrs = unsafe {
+        [
+            &NOCARGO_LINK_ARG as *const u8 as usize,
+            &NOCARGO_LINK_ARG_TESTS as *const u8 as usize,
+        ]
+    };
+    assert_eq!(addrs, [1, 4]);
+}
@@ -0,0 +1,3 @@
+fn main() {
+    assert_eq!(semver::Version::new(1, 2, 3).to_string(), "1.2.3");
+}
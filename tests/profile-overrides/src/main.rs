@@ -0,0 +1,5 @@
+#[cfg(not(debug_assertions))]
+fn main() {
+    assert_eq!(semver::Version::new(1, 2, 3).to_string(), "1.2.3");
+    println!("Hello, world!");
+}
@@ -0,0 +1,4 @@
+#[cfg(not(feature = "cli"))]
+compile_error!("Feature `cli` is required");
+
+fn main() {}
@@ -0,0 +1,2 @@
+pub const COLOR: bool = cfg!(feature = "color");
+pub const UNICODE: bool = cfg!(feature = "unicode");
@@ -0,0 +1,2 @@
+#[cfg(not(feature = "cli"))]
+compile_error!("Feature `cli` is required");
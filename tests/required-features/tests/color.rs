@@ -0,0 +1,4 @@
+#[test]
+fn color() {
+    assert!(term::COLOR);
+}
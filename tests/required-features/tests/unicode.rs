@@ -0,0 +1,4 @@
+#[test]
+fn unicode() {
+    panic!("Feature `term/unicode` is required, but not enabled");
+}
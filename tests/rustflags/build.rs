@@ -0,0 +1,2 @@
+#[cfg(all(target_rustflags, not(build_rustflags)))]
+fn main() {}
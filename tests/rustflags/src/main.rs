@@ -0,0 +1,4 @@
+#[cfg(all(target_rustflags, not(build_rustflags)))]
+fn main() {
+    println!("Hello, world!");
+}
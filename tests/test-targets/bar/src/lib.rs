@@ -0,0 +1,6 @@
+pub fn greeting() -> &'static str {
+    "Hello, world!"
+}
+
+#[cfg(feature = "extra")]
+pub const EXTRA: bool = true;
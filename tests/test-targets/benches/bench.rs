@@ -0,0 +1,6 @@
+fn main() {
+    // Benches are built with the `bench` profile.
+    assert!(!cfg!(debug_assertions));
+    assert!(bar::EXTRA);
+    println!("{}", foo::hello());
+}
@@ -0,0 +1,4 @@
+fn main() {
+    assert_eq!(foo::hello(), bar::greeting());
+    println!("{}", foo::hello());
+}
@@ -0,0 +1,14 @@
+/// ```
+/// assert_eq!(foo::hello(), bar::greeting());
+/// ```
+pub fn hello() -> &'static str {
+    "Hello, world!"
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn dev_dependency() {
+        assert_eq!(super::hello(), bar::greeting());
+    }
+}
@@ -0,0 +1,11 @@
+#[test]
+fn dev_dependency_features() {
+    assert!(bar::EXTRA);
+    assert_eq!(foo::hello(), bar::greeting());
+}
+
+#[test]
+fn run_in_source_dir() {
+    let data = std::fs::read_to_string("tests/data.txt").unwrap();
+    assert_eq!(data.trim(), foo::hello());
+}
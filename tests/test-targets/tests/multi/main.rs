@@ -0,0 +1,6 @@
+mod util;
+
+#[test]
+fn multi_file() {
+    assert_eq!(util::hello(), foo::hello());
+}
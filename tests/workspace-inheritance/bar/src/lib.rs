@@ -0,0 +1,4 @@
+#[cfg(feature = "hello")]
+pub fn hello() -> &'static str {
+    "Hello"
+}
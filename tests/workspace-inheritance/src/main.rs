@@ -0,0 +1,6 @@
+fn main() {
+    // Inherited from `[workspace.package]`.
+    assert_eq!(env!("CARGO_PKG_VERSION"), "0.2.0");
+    assert_eq!(env!("CARGO_PKG_AUTHORS"), "Alice:Bob");
+    println!("{}, world!", bar::hello());
+}
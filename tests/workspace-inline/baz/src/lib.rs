@@ -1,3 +1,4 @@
+/// Print a string, which must start with [`bar::hello`].
 pub fn show(s: String) {
     assert!(s.starts_with(bar::hello()));
     println!("{}", s);
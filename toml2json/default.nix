@@ -49,7 +49,7 @@ in stdenv.mkDerivation {
     run toml-*/src/lib.rs --crate-name toml --crate-type lib \
       --edition=2018 \
       --extern serde=libserde.rlib
-    run ${./src/main.rs} --crate-name toml2json --crate-type bin \
+    run ${./src}/main.rs --crate-name toml2json --crate-type bin \
       --extern serde_json=./libserde_json.rlib \
       --extern toml=libtoml.rlib
   '';
@@ -63,12 +63,129 @@ in stdenv.mkDerivation {
 
   testJson = ''{"hello":{"world":"good"},"target":{"cfg(target = \"good\")":{"foo":"bar"}}}'';
 
+  testWorkspaceToml = ''
+    [workspace.package]
+    version = "1.2.3"
+    license-file = "LICENSE"
+    [workspace.dependencies]
+    foo = { version = "1", default_features = false }
+  '';
+
+  testManifestToml = ''
+    [project]
+    name = "hello"
+    version.workspace = true
+    license-file.workspace = true
+    [dependencies]
+    foo = { workspace = true, features = ["bar"] }
+    [[bin]]
+    name = "hello"
+  '';
+
+  testManifestJson = ''{"bin":[{"edition":"2015","name":"hello"}],"dependencies":{"foo":{"default-features":false,"features":["bar"],"version":"1"}},"package":{"edition":"2015","license-file":"ws/LICENSE","name":"hello","version":"1.2.3"}}'';
+
+  testLockToml = ''
+    version = 3
+    [[package]]
+    name = "foo"
+    version = "0.1.0"
+    dependencies = ["libc 0.1.12", "libc 0.2.95 (registry+https://example.com)", "semver"]
+    [[package]]
+    name = "libc"
+    version = "0.1.12"
+    source = "registry+https://example.com"
+    checksum = "aaaa"
+    [[package]]
+    name = "libc"
+    version = "0.2.95"
+    source = "registry+https://example.com"
+    checksum = "bbbb"
+    [[package]]
+    name = "semver"
+    version = "1.0.4"
+    source = "git+https://example.com/semver?tag=1.0.4#ea9ea80c"
+  '';
+
+  testLockJson = ''{"packages":{"foo":{"checksum":null,"dependencies":["libc 0.1.12 (registry+https://example.com)","libc 0.2.95 (registry+https://example.com)","semver 1.0.4 (git+https://example.com/semver?tag=1.0.4)"],"name":"foo","source":null,"version":"0.1.0"},"libc 0.1.12 (registry+https://example.com)":{"checksum":"aaaa","dependencies":[],"name":"libc","source":"registry+https://example.com","version":"0.1.12"},"libc 0.2.95 (registry+https://example.com)":{"checksum":"bbbb","dependencies":[],"name":"libc","source":"registry+https://example.com","version":"0.2.95"},"semver 1.0.4 (git+https://example.com/semver?tag=1.0.4)":{"checksum":null,"dependencies":[],"name":"semver","source":"git+https://example.com/semver?tag=1.0.4#ea9ea80c","version":"1.0.4"}},"version":3}'';
+
+  # V1 lock with checksums in `[metadata]`.
+  testLockV1Toml = ''
+    [[package]]
+    name = "foo"
+    version = "0.1.0"
+    dependencies = ["libc 0.2.95 (registry+https://example.com)"]
+    [[package]]
+    name = "libc"
+    version = "0.2.95"
+    source = "registry+https://example.com"
+    [metadata]
+    "checksum libc 0.2.95 (registry+https://example.com)" = "cccc"
+  '';
+
+  testLockAmbiguousToml = ''
+    version = 3
+    [[package]]
+    name = "foo"
+    version = "0.1.0"
+    dependencies = ["libc"]
+    [[package]]
+    name = "libc"
+    version = "0.1.12"
+    source = "registry+https://example.com"
+    [[package]]
+    name = "libc"
+    version = "0.2.95"
+    source = "registry+https://example.com"
+  '';
+
   doCheck = true;
   checkPhase = ''
     ./toml2json <<<"$testToml" >out.json
     echo "Got   : $(cat out.json)"
     echo "Expect: $testJson"
     [[ "$(cat out.json)" == "$testJson" ]]
+
+    mkdir ws
+    cat >ws/Cargo.toml <<<"$testWorkspaceToml"
+    ./toml2json --cargo-manifest --workspace-root ws <<<"$testManifestToml" >out.json
+    echo "Got   : $(cat out.json)"
+    echo "Expect: $testManifestJson"
+    [[ "$(cat out.json)" == "$testManifestJson" ]]
+
+    got="$(./toml2json --cargo-manifest --workspace-root ws --get package.version <<<"$testManifestToml")"
+    echo "Got   : $got"
+    [[ "$got" == "1.2.3" ]]
+    got="$(./toml2json --get 'target."cfg(target = \"good\")".foo' <<<"$testToml")"
+    echo "Got   : $got"
+    [[ "$got" == "bar" ]]
+    got="$(./toml2json --cargo-manifest --workspace-root ws --get 'dependencies.foo.features' --lines <<<"$testManifestToml")"
+    echo "Got   : $got"
+    [[ "$got" == "bar" ]]
+    ./toml2json --cargo-manifest --workspace-root ws <<<"$testManifestToml" >manifest.json
+    got="$(./toml2json --json --get 'bin[].edition' --lines manifest.json)"
+    echo "Got   : $got"
+    [[ "$got" == "2015" ]]
+
+    ./toml2json --cargo-lock <<<"$testLockToml" >out.json
+    echo "Got   : $(cat out.json)"
+    echo "Expect: $testLockJson"
+    [[ "$(cat out.json)" == "$testLockJson" ]]
+    got="$(./toml2json --cargo-lock --get 'packages."libc 0.2.95 (registry+https://example.com)".checksum' <<<"$testLockV1Toml")"
+    echo "Got   : $got"
+    [[ "$got" == "cccc" ]]
+    rc=0
+    ./toml2json --cargo-lock <<<"$testLockAmbiguousToml" || rc=$?
+    echo "Exit code for ambiguous lock: $rc"
+    [[ $rc == 1 ]]
+
+    rc=0
+    ./toml2json <<<"foo =" || rc=$?
+    echo "Exit code for syntax error: $rc"
+    [[ $rc == 3 ]]
+    rc=0
+    ./toml2json ./does-not-exist.toml || rc=$?
+    echo "Exit code for missing file: $rc"
+    [[ $rc == 4 ]]
   '';
 
   installPhase = ''
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Exit code for invalid command line arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code for TOML syntax errors.
+pub const EXIT_PARSE: i32 = 3;
+/// Exit code for I/O errors, eg. missing files.
+pub const EXIT_IO: i32 = 4;
+/// Exit code for other errors, eg. invalid manifests or queries.
+pub const EXIT_INVALID: i32 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    Io {
+        path: String,
+        err: io::Error,
+    },
+    Parse {
+        path: String,
+        source: String,
+        err: toml::de::Error,
+    },
+    Invalid(String),
+}
+
+impl Error {
+    pub fn io(path: &str, err: io::Error) -> Self {
+        Error::Io {
+            path: path.to_owned(),
+            err,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Io { .. } => EXIT_IO,
+            Error::Parse { .. } => EXIT_PARSE,
+            Error::Invalid(_) => EXIT_INVALID,
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error::Invalid(msg)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(msg: &'a str) -> Self {
+        Error::Invalid(msg.to_owned())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(msg) | Error::Invalid(msg) => write!(f, "error: {}", msg),
+            Error::Io { path, err } => write!(f, "error: {}: {}", path, err),
+            Error::Parse { path, source, err } => {
+                writeln!(f, "error: failed to parse {}", path)?;
+                let (line, col) = match err.line_col() {
+                    Some(pos) => pos,
+                    None => return write!(f, "  = {}", err),
+                };
+                let text = source.lines().nth(line).unwrap_or("");
+                let col = col.min(text.len());
+                let lineno = (line + 1).to_string();
+                let pad = " ".repeat(lineno.len());
+                // Keep tabs for the caret to align.
+                let caret_pad: String = text
+                    .char_indices()
+                    .take_while(|&(i, _)| i < col)
+                    .map(|(_, c)| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(f, "{}--> {}:{}:{}", pad, path, line + 1, col + 1)?;
+                writeln!(f, "{} |", pad)?;
+                writeln!(f, "{} | {}", lineno, text)?;
+                writeln!(f, "{} | {}^", pad, caret_pad)?;
+                // The position is already shown above.
+                let msg = err.to_string();
+                let pos = format!(" at line {} column {}", line + 1, col + 1);
+                let msg = if msg.ends_with(&pos) {
+                    &msg[..msg.len() - pos.len()]
+                } else {
+                    &msg[..]
+                };
+                write!(f, "{} = {}", pad, msg)
+            }
+        }
+    }
+}
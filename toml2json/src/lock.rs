@@ -0,0 +1,438 @@
+//! Validation and normalization of `Cargo.lock` into a resolved dependency graph.
+//!
+//! The output is,
+//! ```json
+//! {
+//!   "version": 3,
+//!   "packages": {
+//!     "<package id>": {
+//!       "name": "libc",
+//!       "version": "0.2.95",
+//!       "source": "registry+https://github.com/rust-lang/crates.io-index",
+//!       "checksum": "<sha256>",
+//!       "dependencies": ["<package id>"]
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Package ids follow `toPkgId` in `lib/pkg-info.nix`: `name version (source)` with the
+//! `#<rev>` fragment of git sources stripped, or just `name` for local crates.
+//! `source` and `checksum` are `null` for local crates.
+//!
+//! See:
+//! https://github.com/rust-lang/cargo/blob/rust-1.63.0/src/cargo/core/resolver/encode.rs
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use error::Result;
+
+struct Package<'a> {
+    id: String,
+    name: &'a str,
+    version: &'a str,
+    source: Option<&'a str>,
+    // Source without the `#<rev>` fragment, which is used in dependency references.
+    source_ref: Option<&'a str>,
+    checksum: Option<&'a str>,
+    dependencies: Vec<&'a str>,
+}
+
+pub fn pkg_id(name: &str, version: &str, source: Option<&str>) -> String {
+    match source {
+        Some(source) => format!("{} {} ({})", name, version, source),
+        None => name.to_owned(),
+    }
+}
+
+fn strip_fragment(source: &str) -> &str {
+    match source.find('#') {
+        Some(idx) => &source[..idx],
+        None => source,
+    }
+}
+
+fn get_str<'a>(table: &'a Value, key: &str, what: &str) -> Result<Option<&'a str>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("`{}` of {} must be a string", key, what).into()),
+    }
+}
+
+/// Parse a dependency reference `name[ version[ (source)]]`.
+fn parse_dep_ref(s: &str) -> Result<(&str, Option<&str>, Option<&str>)> {
+    let invalid = || format!("Invalid dependency reference: {:?}", s).into();
+    let (rest, source) = if s.ends_with(')') {
+        let idx = s.find(" (").ok_or_else(invalid)?;
+        (&s[..idx], Some(&s[idx + 2..s.len() - 1]))
+    } else {
+        (s, None)
+    };
+    let mut parts = rest.split(' ');
+    let name = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+    let version = parts.next();
+    if parts.next().is_some() || (source.is_some() && version.is_none()) {
+        return Err(invalid());
+    }
+    Ok((name, version, source))
+}
+
+/// Validate a parsed `Cargo.lock` and convert it into the normalized graph.
+pub fn normalize(lock: &Value) -> Result<Value> {
+    // v1 and v2 don't have the `version` field.
+    let version = match lock.get("version") {
+        None => 2,
+        Some(Value::Number(n)) if n.as_u64() == Some(3) => 3,
+        Some(v) => return Err(format!("Unsupported version of Cargo.lock: {}", v).into()),
+    };
+
+    // v1 stores checksums in `[metadata]`.
+    let mut metadata_checksums = BTreeMap::new();
+    if let Some(Value::Object(metadata)) = lock.get("metadata") {
+        for (key, value) in metadata {
+            if key.starts_with("checksum ") {
+                if let Value::String(checksum) = value {
+                    metadata_checksums.insert(&key["checksum ".len()..], &**checksum);
+                }
+            }
+        }
+    }
+
+    let mut raw_pkgs = Vec::new();
+    match lock.get("package") {
+        None => {}
+        Some(Value::Array(pkgs)) => raw_pkgs.extend(pkgs),
+        Some(_) => return Err("`package` must be an array of tables".into()),
+    }
+    // v1 may store the root package separately.
+    if let Some(root) = lock.get("root") {
+        raw_pkgs.push(root);
+    }
+
+    let mut pkgs = Vec::new();
+    for raw in raw_pkgs {
+        let what = "package";
+        let name = get_str(raw, "name", what)?.ok_or("Missing `name` of package")?;
+        let what = format!("package {:?}", name);
+        let version = get_str(raw, "version", &what)?
+            .ok_or_else(|| format!("Missing `version` of {}", what))?;
+        let source = get_str(raw, "source", &what)?;
+        let source_ref = source.map(strip_fragment);
+        let id = pkg_id(name, version, source_ref);
+        let checksum = match get_str(raw, "checksum", &what)? {
+            Some(checksum) => Some(checksum),
+            None => metadata_checksums
+                .get(&*pkg_id(name, version, source))
+                .cloned()
+                .filter(|&c| c != "<none>"),
+        };
+        let dependencies = match raw.get("dependencies") {
+            None => Vec::new(),
+            Some(Value::Array(deps)) => deps
+                .iter()
+                .map(|dep| {
+                    dep.as_str()
+                        .ok_or_else(|| format!("Dependencies of {} must be strings", id))
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            Some(_) => return Err(format!("Dependencies of {} must be an array", id).into()),
+        };
+        pkgs.push(Package {
+            id,
+            name,
+            version,
+            source,
+            source_ref,
+            checksum,
+            dependencies,
+        });
+    }
+
+    let mut out_pkgs = Map::new();
+    for pkg in &pkgs {
+        let mut deps = Vec::new();
+        for dep in &pkg.dependencies {
+            let (name, version, source) = parse_dep_ref(dep)?;
+            let candidates = pkgs
+                .iter()
+                .filter(|p| {
+                    p.name == name
+                        && version.map_or(true, |v| p.version == v)
+                        && source.map_or(true, |s| p.source_ref == Some(s))
+                })
+                .collect::<Vec<_>>();
+            match candidates.len() {
+                0 => {
+                    return Err(format!(
+                        "When resolving {}, locked dependency `{}` not found",
+                        pkg.id, dep
+                    )
+                    .into())
+                }
+                1 => deps.push(Value::String(candidates[0].id.clone())),
+                _ => {
+                    let found = candidates.iter().map(|p| &*p.id).collect::<Vec<_>>();
+                    return Err(format!(
+                        "When resolving {}, locked dependency `{}` is ambiguous. Found: {:?}",
+                        pkg.id, dep, found,
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let mut out = Map::new();
+        out.insert("name".into(), pkg.name.into());
+        out.insert("version".into(), pkg.version.into());
+        out.insert("source".into(), pkg.source.into());
+        out.insert("checksum".into(), pkg.checksum.into());
+        out.insert("dependencies".into(), Value::Array(deps));
+        if out_pkgs
+            .insert(pkg.id.clone(), Value::Object(out))
+            .is_some()
+        {
+            return Err(format!("Duplicated package: {}", pkg.id).into());
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("version".into(), version.into());
+    out.insert("packages".into(), Value::Object(out_pkgs));
+    Ok(Value::Object(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use serde_json::json;
+
+    fn normalize_toml(lock: &str) -> Result<Value> {
+        normalize(&toml::from_str(lock).unwrap())
+    }
+
+    fn expect_err(lock: &str, msg: &str) {
+        match normalize_toml(lock) {
+            Err(Error::Invalid(err)) => assert!(err.contains(msg), "Unexpected error: {}", err),
+            ret => panic!("Expecting an error containing {:?}, got {:?}", msg, ret),
+        }
+    }
+
+    #[test]
+    fn test_parse_dep_ref() {
+        assert_eq!(parse_dep_ref("foo").unwrap(), ("foo", None, None));
+        assert_eq!(
+            parse_dep_ref("foo 1.0.0").unwrap(),
+            ("foo", Some("1.0.0"), None)
+        );
+        assert_eq!(
+            parse_dep_ref("foo 1.0.0 (registry+https://example.com)").unwrap(),
+            ("foo", Some("1.0.0"), Some("registry+https://example.com"))
+        );
+        assert!(parse_dep_ref("").is_err());
+        assert!(parse_dep_ref("foo (registry+https://example.com)").is_err());
+        assert!(parse_dep_ref("foo 1.0.0 extra").is_err());
+    }
+
+    #[test]
+    fn test_v1_metadata_checksums() {
+        let lock = r#"
+[root]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "libc 0.2.95 (registry+https://example.com)",
+ "foo 0.1.0 (git+https://example.com/foo)",
+]
+
+[[package]]
+name = "libc"
+version = "0.2.95"
+source = "registry+https://example.com"
+
+[[package]]
+name = "foo"
+version = "0.1.0"
+source = "git+https://example.com/foo#abcd"
+
+[metadata]
+"checksum libc 0.2.95 (registry+https://example.com)" = "cccc"
+"checksum foo 0.1.0 (git+https://example.com/foo#abcd)" = "<none>"
+"#;
+        let got = normalize_toml(lock).unwrap();
+        assert_eq!(
+            got,
+            json!({
+                "version": 2,
+                "packages": {
+                    "app": {
+                        "name": "app",
+                        "version": "0.1.0",
+                        "source": null,
+                        "checksum": null,
+                        "dependencies": [
+                            "libc 0.2.95 (registry+https://example.com)",
+                            "foo 0.1.0 (git+https://example.com/foo)",
+                        ],
+                    },
+                    "libc 0.2.95 (registry+https://example.com)": {
+                        "name": "libc",
+                        "version": "0.2.95",
+                        "source": "registry+https://example.com",
+                        "checksum": "cccc",
+                        "dependencies": [],
+                    },
+                    "foo 0.1.0 (git+https://example.com/foo)": {
+                        "name": "foo",
+                        "version": "0.1.0",
+                        "source": "git+https://example.com/foo#abcd",
+                        "checksum": null,
+                        "dependencies": [],
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_v3_short_ids() {
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "foo",
+ "libc 0.1.0",
+ "libc 0.2.95",
+ "local",
+]
+
+[[package]]
+name = "local"
+version = "0.1.0"
+
+[[package]]
+name = "foo"
+version = "0.1.0"
+source = "git+https://example.com/foo?branch=main#abcd"
+
+[[package]]
+name = "libc"
+version = "0.1.0"
+source = "registry+https://example.com"
+checksum = "aaaa"
+
+[[package]]
+name = "libc"
+version = "0.2.95"
+source = "registry+https://example.com"
+checksum = "bbbb"
+"#;
+        let got = normalize_toml(lock).unwrap();
+        assert_eq!(got["version"], 3);
+        let pkgs = &got["packages"];
+        assert_eq!(
+            pkgs["app"]["dependencies"],
+            json!([
+                "foo 0.1.0 (git+https://example.com/foo?branch=main)",
+                "libc 0.1.0 (registry+https://example.com)",
+                "libc 0.2.95 (registry+https://example.com)",
+                "local",
+            ])
+        );
+        // Path crates have neither source nor checksum.
+        assert_eq!(pkgs["local"]["source"], Value::Null);
+        assert_eq!(pkgs["local"]["checksum"], Value::Null);
+        // The fragment is only stripped from the id.
+        assert_eq!(
+            pkgs["foo 0.1.0 (git+https://example.com/foo?branch=main)"]["source"],
+            "git+https://example.com/foo?branch=main#abcd"
+        );
+        assert_eq!(
+            pkgs["libc 0.2.95 (registry+https://example.com)"]["checksum"],
+            "bbbb"
+        );
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let pkgs = r#"
+[[package]]
+name = "libc"
+version = "0.2.95"
+source = "registry+https://a.example.com"
+
+[[package]]
+name = "libc"
+version = "0.2.95"
+source = "registry+https://b.example.com"
+"#;
+        expect_err(
+            &format!(
+                "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\ndependencies = [\"libc\"]\n{}",
+                pkgs
+            ),
+            "locked dependency `libc` is ambiguous",
+        );
+        expect_err(
+            &format!(
+                "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\ndependencies = [\"libc 0.2.95\"]\n{}",
+                pkgs
+            ),
+            "locked dependency `libc 0.2.95` is ambiguous",
+        );
+
+        // Fully qualified references are never ambiguous.
+        let lock = format!(
+            "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\ndependencies = [\"libc 0.2.95 (registry+https://b.example.com)\"]\n{}",
+            pkgs
+        );
+        let got = normalize_toml(&lock).unwrap();
+        assert_eq!(
+            got["packages"]["app"]["dependencies"],
+            json!(["libc 0.2.95 (registry+https://b.example.com)"])
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        expect_err("version = 100", "Unsupported version of Cargo.lock: 100");
+        expect_err("package = 1", "`package` must be an array of tables");
+        expect_err(
+            "[[package]]\nversion = \"0.1.0\"",
+            "Missing `name` of package",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"",
+            "Missing `version` of package \"foo\"",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = 1",
+            "`version` of package \"foo\" must be a string",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = \"0.1.0\"\ndependencies = \"bar\"",
+            "Dependencies of foo must be an array",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = \"0.1.0\"\ndependencies = [1]",
+            "Dependencies of foo must be strings",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = \"0.1.0\"\ndependencies = [\"bar\"]",
+            "When resolving foo, locked dependency `bar` not found",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = \"0.1.0\"\ndependencies = [\"bar (x)\"]",
+            "Invalid dependency reference: \"bar (x)\"",
+        );
+        expect_err(
+            "[[package]]\nname = \"foo\"\nversion = \"0.1.0\"\n[[package]]\nname = \"foo\"\nversion = \"0.2.0\"",
+            "Duplicated package: foo",
+        );
+    }
+}
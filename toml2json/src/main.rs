@@ -1,11 +1,187 @@
+extern crate serde_json;
+extern crate toml;
+
+use std::env;
+use std::fs;
 use std::io::{stdin, stdout, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+mod error;
+mod lock;
+mod manifest;
+mod query;
+
+use error::{Error, Result};
+
+const USAGE: &str = "\
+Usage: toml2json [--cargo-manifest [--workspace-root <dir>] | --cargo-lock | --json] [--get <path> [--lines]] [<input.toml>]
+
+Convert TOML from <input.toml> (default to stdin) into JSON, or print the field selected by `--get`.
+
+Options:
+    --cargo-manifest          Normalize the input as a Cargo.toml.
+    --workspace-root <dir>    The directory containing the workspace root Cargo.toml,
+                              to inherit `workspace = true` fields from.
+    --cargo-lock              Validate the input as a Cargo.lock and output the resolved
+                              dependency graph keyed by fully qualified package ids.
+    --json                    Read the input as JSON instead, eg. the output of a previous
+                              run, to query it with `--get` without normalizing again.
+    --get <path>              Print the scalar field at <path>, eg. `package.edition`,
+                              `lib.\"crate-type\"` or `bin[].name`. Strings are printed raw,
+                              and missing fields print nothing.
+    --lines                   Allow multiple values, printing each on its own line.
+                              Arrays are flattened. When <path> iterates with `[]`,
+                              missing fields print empty lines.
+
+Exit status:
+    1    Invalid manifest, lock file or query.
+    2    Invalid arguments.
+    3    TOML syntax error.
+    4    I/O error.
+";
+
+/// Read a file, or stdin if `path` is `None`. Return the displayed path and the content.
+fn read_source(path: Option<&Path>) -> Result<(String, String)> {
+    match path {
+        Some(path) => {
+            let display = path.display().to_string();
+            let source = fs::read_to_string(path).map_err(|err| Error::io(&display, err))?;
+            Ok((display, source))
+        }
+        None => {
+            let mut source = String::new();
+            stdin()
+                .lock()
+                .read_to_string(&mut source)
+                .map_err(|err| Error::io("<stdin>", err))?;
+            Ok(("<stdin>".to_owned(), source))
+        }
+    }
+}
+
+/// Read and parse a TOML file, or stdin if `path` is `None`.
+pub fn read_toml(path: Option<&Path>) -> Result<serde_json::Value> {
+    let (display, source) = read_source(path)?;
+    match toml::from_str(&source) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(Error::Parse {
+            path: display,
+            source,
+            err,
+        }),
+    }
+}
+
+/// Read and parse a JSON file, or stdin if `path` is `None`.
+fn read_json(path: Option<&Path>) -> Result<serde_json::Value> {
+    let (display, source) = read_source(path)?;
+    serde_json::from_str(&source)
+        .map_err(|err| Error::Invalid(format!("failed to parse {}: {}", display, err)))
+}
+
+fn main() {
+    if let Err(err) = run() {
+        let _ = stdout().flush();
+        eprintln!("{}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+fn run() -> Result<()> {
+    let mut cargo_manifest = false;
+    let mut cargo_lock = false;
+    let mut json = false;
+    let mut workspace_root = None;
+    let mut get = None;
+    let mut lines = false;
+    let mut input = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--cargo-manifest" => cargo_manifest = true,
+            "--cargo-lock" => cargo_lock = true,
+            "--json" => json = true,
+            "--workspace-root" => {
+                let root = args.next().ok_or_else(|| missing_value(&arg))?;
+                workspace_root = Some(PathBuf::from(root));
+            }
+            "--get" => {
+                let path = args.next().ok_or_else(|| missing_value(&arg))?;
+                get = Some(query::parse_path(&path)?);
+            }
+            "--lines" => lines = true,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            _ if !arg.starts_with('-') && input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => {
+                return Err(Error::Usage(format!(
+                    "Unexpected argument: {}\n\n{}",
+                    arg, USAGE
+                )))
+            }
+        }
+    }
+    if cargo_manifest && cargo_lock {
+        return Err(Error::Usage(
+            "`--cargo-manifest` conflicts with `--cargo-lock`".into(),
+        ));
+    }
+    if json && (cargo_manifest || cargo_lock) {
+        return Err(Error::Usage(
+            "`--json` conflicts with `--cargo-manifest` and `--cargo-lock`".into(),
+        ));
+    }
+    if workspace_root.is_some() && !cargo_manifest {
+        return Err(Error::Usage(
+            "`--workspace-root` requires `--cargo-manifest`".into(),
+        ));
+    }
+    if lines && get.is_none() {
+        return Err(Error::Usage("`--lines` requires `--get`".into()));
+    }
+
+    let mut data = if json {
+        read_json(input.as_ref().map(|p| &**p))?
+    } else {
+        read_toml(input.as_ref().map(|p| &**p))?
+    };
+
+    if cargo_manifest {
+        let workspace = match workspace_root {
+            Some(root) => Some(manifest::Workspace::load(&root)?),
+            None => None,
+        };
+        manifest::normalize(&mut data, workspace.as_ref()).map_err(|err| locate(err, &input))?;
+    } else if cargo_lock {
+        data = lock::normalize(&data).map_err(|err| locate(err, &input))?;
+    }
+
+    let stdout = stdout();
+    let mut out = stdout.lock();
+    if let Some(path) = get {
+        let values = query::select(&data, &path)?;
+        query::print(&mut out, &path, &values, lines)?;
+    } else {
+        let mut output =
+            serde_json::to_vec(&data).map_err(|err| Error::Invalid(err.to_string()))?;
+        output.push(b'\n');
+        out.write_all(&output)
+            .map_err(|err| Error::io("<stdout>", err))?;
+    }
+    out.flush().map_err(|err| Error::io("<stdout>", err))
+}
+
+/// Point validation errors at the offending input file.
+fn locate(err: Error, input: &Option<PathBuf>) -> Error {
+    match (err, input) {
+        (Error::Invalid(msg), Some(path)) => Error::Invalid(format!("{}: {}", path.display(), msg)),
+        (err, _) => err,
+    }
+}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut input = Vec::new();
-    stdin().lock().read_to_end(&mut input)?;
-    let data: serde_json::Value = toml::from_slice(&input)?;
-    let mut output = serde_json::to_vec(&data)?;
-    output.push(b'\n');
-    stdout().lock().write_all(&output)?;
-    Ok(())
+fn missing_value(arg: &str) -> Error {
+    Error::Usage(format!("Missing argument for `{}`", arg))
 }
@@ -0,0 +1,256 @@
+//! Normalization of `Cargo.toml`, so that builders can query fields without knowing
+//! the details of cargo's manifest format.
+//!
+//! - `[project]` is renamed to `[package]`.
+//! - Snake case aliases like `default_features` are renamed to the kebab case ones.
+//! - `workspace = true` fields and dependencies are inherited from the workspace root manifest.
+//! - `package.edition` and `edition` of targets default to the package edition (or "2015").
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use super::read_toml;
+use error::Result;
+
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+const TARGET_LISTS: &[&str] = &["bin", "example", "test", "bench"];
+
+// https://github.com/rust-lang/cargo/blob/rust-1.63.0/src/cargo/util/toml/mod.rs
+const MANIFEST_ALIASES: &[(&str, &str)] = &[
+    ("dev_dependencies", "dev-dependencies"),
+    ("build_dependencies", "build-dependencies"),
+];
+const DEPENDENCY_ALIASES: &[(&str, &str)] = &[("default_features", "default-features")];
+const TARGET_ALIASES: &[(&str, &str)] =
+    &[("proc_macro", "proc-macro"), ("crate_type", "crate-type")];
+
+// Path fields are relative to the manifest defining them.
+const PACKAGE_PATH_FIELDS: &[&str] = &["readme", "license-file"];
+
+/// The inheritable parts of `[workspace]` in the workspace root manifest.
+/// https://doc.rust-lang.org/cargo/reference/workspaces.html#the-package-table
+pub struct Workspace {
+    root: PathBuf,
+    package: Map<String, Value>,
+    dependencies: Map<String, Value>,
+}
+
+impl Workspace {
+    /// Load the workspace definition from `<root>/Cargo.toml`.
+    pub fn load(root: &Path) -> Result<Self> {
+        let manifest = read_toml(Some(&root.join("Cargo.toml")))?;
+        let get_table = |key: &str| {
+            manifest
+                .get("workspace")
+                .and_then(|ws| ws.get(key))
+                .and_then(|v| v.as_object())
+                .cloned()
+                .unwrap_or_default()
+        };
+        Ok(Self {
+            root: root.to_owned(),
+            package: get_table("package"),
+            dependencies: get_table("dependencies"),
+        })
+    }
+
+    fn rebase_path(&self, path: &str) -> Result<Value> {
+        let path = self.root.join(path);
+        match path.to_str() {
+            Some(path) => Ok(Value::String(path.to_owned())),
+            None => Err(format!("Non UTF-8 path is not supported: {}", path.display()).into()),
+        }
+    }
+
+    fn inherit_field(&self, field: &str) -> Result<Value> {
+        let value = match self.package.get(field) {
+            Some(value) => value,
+            None => {
+                return Err(format!(
+                    "`package.{}` is inherited but not defined in `[workspace.package]`",
+                    field,
+                )
+                .into())
+            }
+        };
+        match value {
+            Value::String(path) if PACKAGE_PATH_FIELDS.contains(&field) => self.rebase_path(path),
+            _ => Ok(value.clone()),
+        }
+    }
+
+    // https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#inheriting-a-dependency-from-a-workspace
+    fn inherit_dependency(&self, name: &str, dep: &Map<String, Value>) -> Result<Value> {
+        let mut inherited = match self.dependencies.get(name) {
+            Some(Value::String(req)) => {
+                let mut detail = Map::new();
+                detail.insert("version".into(), Value::String(req.clone()));
+                detail
+            }
+            Some(Value::Object(detail)) => {
+                let mut detail = detail.clone();
+                rename_aliases(&mut detail, DEPENDENCY_ALIASES);
+                detail
+            }
+            Some(_) => {
+                return Err(format!(
+                    "Invalid dependency `{}` in `[workspace.dependencies]`",
+                    name
+                )
+                .into())
+            }
+            None => {
+                return Err(format!(
+                    "Dependency `{}` is inherited but not defined in `[workspace.dependencies]`",
+                    name,
+                )
+                .into())
+            }
+        };
+
+        let path = match inherited.get("path") {
+            Some(Value::String(path)) => Some(self.rebase_path(path)?),
+            _ => None,
+        };
+        if let Some(path) = path {
+            inherited.insert("path".into(), path);
+        }
+
+        // Only `features` and `optional` are allowed along with `workspace = true`.
+        // `features` are additive, and `default-features = true` re-enables the default features.
+        if let Some(Value::Array(features)) = dep.get("features") {
+            match inherited
+                .entry("features")
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(inherited_features) => {
+                    inherited_features.extend(features.iter().cloned())
+                }
+                _ => {
+                    return Err(
+                        format!("Invalid `features` of workspace dependency `{}`", name).into(),
+                    )
+                }
+            }
+        }
+        if let Some(optional) = dep.get("optional") {
+            inherited.insert("optional".into(), optional.clone());
+        }
+        if dep.get("default-features").and_then(|v| v.as_bool()) == Some(true) {
+            inherited.insert("default-features".into(), Value::Bool(true));
+        }
+
+        Ok(Value::Object(inherited))
+    }
+}
+
+/// Normalize a parsed `Cargo.toml` in place.
+pub fn normalize(manifest: &mut Value, workspace: Option<&Workspace>) -> Result<()> {
+    let manifest = match manifest.as_object_mut() {
+        Some(manifest) => manifest,
+        None => return Err("Cargo.toml must be a table".into()),
+    };
+
+    // Some old crates use `[project]` instead of `[package]`.
+    if !manifest.contains_key("package") {
+        if let Some(project) = manifest.remove("project") {
+            manifest.insert("package".into(), project);
+        }
+    }
+
+    rename_aliases(manifest, MANIFEST_ALIASES);
+
+    let mut edition = None;
+    if let Some(package) = manifest.get_mut("package").and_then(|v| v.as_object_mut()) {
+        for (field, value) in package.iter_mut() {
+            if is_inherited(value) {
+                *value = inherit(workspace, &format!("package.{}", field))
+                    .and_then(|ws| ws.inherit_field(field))?;
+            }
+        }
+        edition = Some(
+            package
+                .entry("edition")
+                .or_insert_with(|| Value::String("2015".into()))
+                .clone(),
+        );
+    }
+
+    normalize_dependency_tables(manifest, workspace)?;
+    if let Some(targets) = manifest.get_mut("target").and_then(|v| v.as_object_mut()) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_object_mut() {
+                rename_aliases(target, MANIFEST_ALIASES);
+                normalize_dependency_tables(target, workspace)?;
+            }
+        }
+    }
+
+    if let Some(lib) = manifest.get_mut("lib").and_then(|v| v.as_object_mut()) {
+        normalize_target(lib, edition.as_ref());
+    }
+    for key in TARGET_LISTS {
+        if let Some(targets) = manifest.get_mut(*key).and_then(|v| v.as_array_mut()) {
+            for target in targets.iter_mut().filter_map(|v| v.as_object_mut()) {
+                normalize_target(target, edition.as_ref());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn inherit<'a>(workspace: Option<&'a Workspace>, what: &str) -> Result<&'a Workspace> {
+    match workspace {
+        Some(ws) => Ok(ws),
+        None => Err(format!(
+            "`{}` is inherited from the workspace, but `--workspace-root` is not specified",
+            what,
+        )
+        .into()),
+    }
+}
+
+fn is_inherited(value: &Value) -> bool {
+    value.get("workspace").and_then(|v| v.as_bool()) == Some(true)
+}
+
+fn rename_aliases(table: &mut Map<String, Value>, aliases: &[(&str, &str)]) {
+    for &(from, to) in aliases {
+        if let Some(value) = table.remove(from) {
+            // The kebab case one takes precedence, as cargo does.
+            table.entry(to).or_insert(value);
+        }
+    }
+}
+
+fn normalize_dependency_tables(
+    table: &mut Map<String, Value>,
+    workspace: Option<&Workspace>,
+) -> Result<()> {
+    for key in DEPENDENCY_TABLES {
+        let deps = match table.get_mut(*key).and_then(|v| v.as_object_mut()) {
+            Some(deps) => deps,
+            None => continue,
+        };
+        for (name, dep) in deps.iter_mut() {
+            let detail = match dep.as_object_mut() {
+                Some(detail) => detail,
+                None => continue,
+            };
+            rename_aliases(detail, DEPENDENCY_ALIASES);
+            if is_inherited(dep) {
+                let ws = inherit(workspace, &format!("{}.{}", key, name))?;
+                *dep = ws.inherit_dependency(name, dep.as_object().unwrap())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn normalize_target(target: &mut Map<String, Value>, edition: Option<&Value>) {
+    rename_aliases(target, TARGET_ALIASES);
+    if let Some(edition) = edition {
+        target.entry("edition").or_insert_with(|| edition.clone());
+    }
+}
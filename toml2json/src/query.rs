@@ -0,0 +1,174 @@
+//! A tiny query language for fields, to print them in shell-friendly form.
+//!
+//! A path is a sequence of segments separated by `.`, with an optional leading `.`.
+//! - `key` or `"key"` selects a field of a table. Unquoted keys may contain `A-Za-z0-9_-`.
+//!   Quoted keys may contain `\"` and `\\` escapes.
+//! - `[N]` selects the N-th element of an array.
+//! - `[]` iterates over all elements of an array.
+//!
+//! Eg. `package.edition`, `lib."crate-type"`, `bin[].name`.
+use std::io::Write;
+
+use serde_json::Value;
+
+use error::{Error, Result};
+
+static NULL: Value = Value::Null;
+
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+    Iter,
+}
+
+pub fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let invalid = || Error::Usage(format!("Invalid query path: {}", path));
+    let mut segments = Vec::new();
+    let mut rest = path;
+    if rest.starts_with('.') {
+        rest = &rest[1..];
+    }
+    while !rest.is_empty() {
+        if rest.starts_with('"') {
+            // `\"` and `\\` escapes are supported inside quotes.
+            let mut key = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let end = loop {
+                match chars.next().ok_or_else(invalid)? {
+                    (i, '"') => break i,
+                    (_, '\\') => key.push(chars.next().ok_or_else(invalid)?.1),
+                    (_, c) => key.push(c),
+                }
+            };
+            segments.push(Segment::Key(key));
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(Segment::Key(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+
+        while rest.starts_with('[') {
+            let end = rest.find(']').ok_or_else(invalid)?;
+            let index = &rest[1..end];
+            if index.is_empty() {
+                segments.push(Segment::Iter);
+            } else {
+                segments.push(Segment::Index(index.parse().map_err(|_| invalid())?));
+            }
+            rest = &rest[end + 1..];
+        }
+
+        if rest.starts_with('.') {
+            rest = &rest[1..];
+            if rest.is_empty() {
+                return Err(invalid());
+            }
+        } else if !rest.is_empty() {
+            return Err(invalid());
+        }
+    }
+    Ok(segments)
+}
+
+/// Select values by the path. Missing fields result in `null`.
+/// Iterating over `null` results in nothing.
+pub fn select<'a>(value: &'a Value, path: &[Segment]) -> Result<Vec<&'a Value>> {
+    let mut values = vec![value];
+    for seg in path {
+        let mut next = Vec::new();
+        for value in values {
+            match (seg, value) {
+                (Segment::Key(key), Value::Object(map)) => next.push(map.get(key).unwrap_or(&NULL)),
+                (Segment::Index(idx), Value::Array(arr)) => {
+                    next.push(arr.get(*idx).unwrap_or(&NULL))
+                }
+                (Segment::Iter, Value::Array(arr)) => next.extend(arr),
+                (Segment::Key(_), Value::Null) | (Segment::Index(_), Value::Null) => {
+                    next.push(&NULL)
+                }
+                (Segment::Iter, Value::Null) => {}
+                (seg, value) => {
+                    return Err(format!("Cannot select {:?} from {}", seg, type_name(value)).into())
+                }
+            }
+        }
+        values = next;
+    }
+    Ok(values)
+}
+
+/// Print selected values.
+///
+/// Without `lines`, there must be at most one selected value, which must be a scalar.
+/// Strings are printed raw, other scalars are printed in JSON, and `null` prints nothing.
+///
+/// With `lines`, each scalar is printed on its own line and arrays are flattened.
+/// If the path iterates, each `null` prints an empty line, so that outputs of
+/// `bin[].name` and `bin[].path` always line up.
+pub fn print(out: &mut dyn Write, path: &[Segment], values: &[&Value], lines: bool) -> Result<()> {
+    if !lines {
+        if values.len() > 1 {
+            return Err("Multiple values are selected. Use `--lines` to print them".into());
+        }
+        if let Some(value) = values.first().filter(|v| !v.is_null()) {
+            writeln!(out, "{}", scalar_to_string(value)?)
+                .map_err(|err| Error::io("<stdout>", err))?;
+        }
+        return Ok(());
+    }
+
+    let iterated = path.contains(&Segment::Iter);
+    for value in values {
+        match value {
+            Value::Null if !iterated => {}
+            Value::Array(arr) => {
+                for elem in arr {
+                    write_line(out, elem)?;
+                }
+            }
+            _ => write_line(out, value)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_line(out: &mut dyn Write, value: &Value) -> Result<()> {
+    let s = scalar_to_string(value)?;
+    if s.contains('\n') {
+        return Err(format!(
+            "Multi-line string cannot be printed with `--lines`: {:?}",
+            s
+        )
+        .into());
+    }
+    writeln!(out, "{}", s).map_err(|err| Error::io("<stdout>", err))
+}
+
+fn scalar_to_string(value: &Value) -> Result<String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Object(_) => {
+            Err(format!("Expect a scalar, found {}", type_name(value)).into())
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "a table",
+    }
+}